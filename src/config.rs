@@ -1,6 +1,9 @@
-use std::{ops::Deref, path::PathBuf, str::FromStr};
+use std::{fmt::Display, ops::Deref, path::PathBuf, str::FromStr, time::Duration};
 
-use crate::{tests::report::TestReport, NostrClient};
+use crate::{
+    tests::{nip11::Information, report::TestReport},
+    NostrClient,
+};
 use clap::Parser;
 use clap_serde_derive::ClapSerde;
 use color_eyre::eyre::anyhow;
@@ -11,6 +14,7 @@ use serde::{Deserialize, Serialize};
 use url::Url;
 
 const DEFAULT_PRIVATE_KEY: &str = "nsec1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqsmhltgl";
+const DEFAULT_TIMEOUT_SECS: u64 = 10;
 
 pub static DEFAULT_CONFIG_PATH: Lazy<PathBuf> = Lazy::new(|| PathBuf::from("nostr-relay-tester.toml"));
 
@@ -38,8 +42,106 @@ pub struct Config {
     // required due to annoying trait bounds
     #[arg(short, long, help = "64-character bech32-encoded private key", default_value = DEFAULT_PRIVATE_KEY)]
     pub key: NostrKeys,
-    #[arg(short, long, value_delimiter = ',', default_value = "nip01,nip09")]
-    pub nips: Vec<Nips>, // Cannot make this a HashSet due to trait bounds
+    #[default(vec![NipSelection::Nip(Nips::Nip01), NipSelection::Nip(Nips::Nip09)])]
+    #[arg(
+        short,
+        long,
+        value_delimiter = ',',
+        help = "NIPs to test, `auto` standing for every testable NIP in the relay's supported_nips [default: \
+                nip01,nip09]"
+    )]
+    pub nips: Vec<NipSelection>, // Cannot make this a HashSet due to trait bounds
+    // `default_value` would override the config file, so the default is documented in the help text instead
+    #[default(DEFAULT_TIMEOUT_SECS)]
+    #[arg(short, long, help = "Seconds to wait for each expected relay response [default: 10]")]
+    pub timeout: u64,
+    #[arg(
+        long,
+        value_delimiter = ',',
+        help = "Per-NIP overrides for --timeout [example: nip01=5,nip09=20]"
+    )]
+    pub nip_timeouts: Vec<NipTimeout>,
+    #[arg(
+        long,
+        help = "Proof-of-work difficulty the NIP-13 test expects the relay to require, instead of its advertised \
+                min_pow_difficulty"
+    )]
+    pub pow_difficulty: Option<u8>,
+}
+
+impl Config {
+    /// How long tests for `nip` should wait on any single relay response.
+    pub fn timeout_for(&self, nip: Nips) -> Duration {
+        let secs = self
+            .nip_timeouts
+            .iter()
+            .rev()
+            .find(|nip_timeout| nip_timeout.nip == nip)
+            .map_or(self.timeout, |nip_timeout| nip_timeout.seconds);
+
+        Duration::from_secs(secs)
+    }
+}
+
+/// A `--timeout` override for a single NIP.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub struct NipTimeout {
+    pub nip: Nips,
+    pub seconds: u64,
+}
+
+impl FromStr for NipTimeout {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (nip, seconds) = s.split_once('=').ok_or(anyhow!("Expected <nip>=<seconds>, got: {s}"))?;
+
+        Ok(NipTimeout {
+            nip: nip.trim().parse()?,
+            seconds: seconds
+                .trim()
+                .parse()
+                .map_err(|e| anyhow!("Invalid timeout for {nip}: {e}"))?,
+        })
+    }
+}
+
+impl TryFrom<String> for NipTimeout {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
+}
+
+/// An entry of `--nips`.
+#[derive(Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
+#[serde(try_from = "String")]
+pub enum NipSelection {
+    /// Every NIP the tester implements that the relay advertises in its
+    /// relay information document.
+    Auto,
+    Nip(Nips),
+}
+
+impl FromStr for NipSelection {
+    type Err = color_eyre::eyre::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(NipSelection::Auto),
+            _ => Ok(NipSelection::Nip(s.parse()?)),
+        }
+    }
+}
+
+impl TryFrom<String> for NipSelection {
+    type Error = color_eyre::eyre::Error;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        s.parse()
+    }
 }
 
 #[derive(Clone)]
@@ -78,31 +180,95 @@ impl<'de> Deserialize<'de> for NostrKeys {
     }
 }
 
-#[derive(Serialize, Deserialize, Copy, Clone, Debug)]
+#[derive(Serialize, Deserialize, Copy, Clone, Debug, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 /// Supported NIPs
 pub enum Nips {
     Nip01,
     Nip02,
     Nip09,
+    Nip11,
+    Nip13,
+    Nip22,
+    Nip40,
+    Nip42,
+    Nip45,
+    Nip50,
+    Nip70,
 }
 
 impl Nips {
-    pub async fn test(&self, client: &NostrClient, relay: &Relay) -> TestReport {
+    /// Every NIP the tester implements.
+    pub const ALL: [Nips; 11] = [
+        Nips::Nip01,
+        Nips::Nip02,
+        Nips::Nip09,
+        Nips::Nip11,
+        Nips::Nip13,
+        Nips::Nip22,
+        Nips::Nip40,
+        Nips::Nip42,
+        Nips::Nip45,
+        Nips::Nip50,
+        Nips::Nip70,
+    ];
+
+    pub async fn test(
+        &self,
+        client: &NostrClient,
+        relay: &Relay,
+        config: &Config,
+        information: Information<'_>,
+    ) -> TestReport {
+        let timeout = config.timeout_for(*self);
+
         /// If you hate this, blame [Tricked](https://github.com/Tricked-dev/) for encouraging me
         macro_rules! match_and_test {
-            ($($number:literal )*) => {
+            ($($number:literal )*; $($special:tt)*) => {
                 paste::paste! {
                     match self {
                         $(
-                            Nips::[<Nip $number>] => crate::tests::[<nip $number>]::test(client, relay).await,
+                            Nips::[<Nip $number>] => crate::tests::[<nip $number>]::test(client, relay, timeout).await,
                         )*
+                        $($special)*
                     }
                 }
             }
         }
 
-        match_and_test!(01 02 09)
+        // Tests that need more than their timeout are matched by hand
+        match_and_test!(01 02 09 40 42 45 50 70;
+            Nips::Nip11 => crate::tests::nip11::test(information),
+            Nips::Nip13 => crate::tests::nip13::test(client, relay, timeout, config.pow_difficulty, information).await,
+            Nips::Nip22 => crate::tests::nip22::test(client, relay, timeout, information).await,
+        )
+    }
+
+    /// The NIP's number, as it appears in a relay's `supported_nips`.
+    pub fn number(&self) -> u16 {
+        match self {
+            Nips::Nip01 => 1,
+            Nips::Nip02 => 2,
+            Nips::Nip09 => 9,
+            Nips::Nip11 => 11,
+            Nips::Nip13 => 13,
+            Nips::Nip22 => 22,
+            Nips::Nip40 => 40,
+            Nips::Nip42 => 42,
+            Nips::Nip45 => 45,
+            Nips::Nip50 => 50,
+            Nips::Nip70 => 70,
+        }
+    }
+
+    pub fn from_number(number: u16) -> Option<Nips> {
+        Nips::ALL.into_iter().find(|nip| nip.number() == number)
+    }
+}
+
+impl Display for Nips {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.pad(&format!("NIP-{:02}", self.number()))
     }
 }
 
@@ -114,6 +280,14 @@ impl FromStr for Nips {
             "nip01" => Ok(Nips::Nip01),
             "nip02" => Ok(Nips::Nip02),
             "nip09" => Ok(Nips::Nip09),
+            "nip11" => Ok(Nips::Nip11),
+            "nip13" => Ok(Nips::Nip13),
+            "nip22" => Ok(Nips::Nip22),
+            "nip40" => Ok(Nips::Nip40),
+            "nip42" => Ok(Nips::Nip42),
+            "nip45" => Ok(Nips::Nip45),
+            "nip50" => Ok(Nips::Nip50),
+            "nip70" => Ok(Nips::Nip70),
             _ => Err(anyhow!("Not a supported NIP: {s}")),
         }
     }
@@ -143,3 +317,63 @@ mod deser {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Parses a config file the way `main` does.
+    fn config(toml: &str) -> Config {
+        Config::from(toml::from_str::<<Config as ClapSerde>::Opt>(toml).unwrap())
+    }
+
+    #[test]
+    fn nip_timeout_parses() {
+        assert_eq!(
+            "nip01=5".parse::<NipTimeout>().unwrap(),
+            NipTimeout {
+                nip: Nips::Nip01,
+                seconds: 5
+            }
+        );
+        assert_eq!(
+            " NIP11 = 2 ".parse::<NipTimeout>().unwrap(),
+            NipTimeout {
+                nip: Nips::Nip11,
+                seconds: 2
+            }
+        );
+    }
+
+    #[test]
+    fn nip_timeout_rejects_malformed() {
+        for s in ["nip01", "nip01=", "nip01=-1", "nip01=five", "nip99=5", "=5"] {
+            assert!(s.parse::<NipTimeout>().is_err(), "{s} parsed");
+        }
+    }
+
+    #[test]
+    fn nip_timeouts_deserialize_from_config_file() {
+        let config = config(
+            r#"
+            timeout = 7
+            nip_timeouts = ["nip11=2", "nip40=30"]
+            "#,
+        );
+
+        assert_eq!(config.timeout_for(Nips::Nip11), Duration::from_secs(2));
+        assert_eq!(config.timeout_for(Nips::Nip40), Duration::from_secs(30));
+        assert_eq!(config.timeout_for(Nips::Nip01), Duration::from_secs(7));
+    }
+
+    #[test]
+    fn timeout_for_takes_the_last_override() {
+        let config = config(r#"nip_timeouts = ["nip01=5", "nip01=20"]"#);
+
+        assert_eq!(config.timeout_for(Nips::Nip01), Duration::from_secs(20));
+        assert_eq!(
+            config.timeout_for(Nips::Nip09),
+            Duration::from_secs(DEFAULT_TIMEOUT_SECS)
+        );
+    }
+}
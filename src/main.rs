@@ -1,6 +1,9 @@
 use clap::Parser;
 use clap_serde_derive::ClapSerde;
-use nostr_relay_tester::config::{CliArgs, Config, DEFAULT_CONFIG_PATH};
+use nostr_relay_tester::{
+    config::{CliArgs, Config, DEFAULT_CONFIG_PATH},
+    tests::report::TestSummary,
+};
 use tokio::{fs::File, io::AsyncReadExt};
 use tracing::warn;
 
@@ -29,10 +32,9 @@ async fn main() -> color_eyre::Result<()> {
         }
     };
 
-    nostr_relay_tester::run(config)
-        .await?
-        .iter()
-        .for_each(|result| println!("{result}"));
+    let run = nostr_relay_tester::run(config).await?;
+
+    println!("{}", TestSummary(&run));
 
     Ok(())
 }
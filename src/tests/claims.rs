@@ -0,0 +1,512 @@
+use nostr::{
+    nips::nip13,
+    serde_json::{json, Value},
+};
+
+use crate::tests::{
+    nip11::RelayInformation,
+    prelude::*,
+    raw::{Frame, RawConnection},
+    report::{CaseReport, Status},
+};
+
+/// The most subscriptions opened to check `max_subscriptions`, so that a
+/// generous limit doesn't take thousands of round trips.
+const MAX_PROBED_SUBSCRIPTIONS: u64 = 100;
+
+/// The longest message built to check `max_message_length`, so that an absurd
+/// limit doesn't make the tester allocate gigabytes.
+const MAX_PROBED_MESSAGE_LENGTH: u64 = 16 * 1024 * 1024;
+
+/// The longest subscription ID built to check `max_subid_length`.
+const MAX_PROBED_SUBID_LENGTH: u64 = 4096;
+
+/// How the relay answered a `REQ` sent over a raw connection.
+enum Answer {
+    /// `EOSE`, with the events sent before it.
+    Accepted(Vec<Value>),
+    /// `CLOSED`, or a `NOTICE` mentioning the subscription, with their
+    /// message, or the end of the connection.
+    Refused(String),
+    Silence,
+}
+
+/// Checks what the relay information document claims against how the relay
+/// actually behaves: every advertised NIP that was tested must have passed,
+/// and every advertised limitation must hold.
+pub async fn check(
+    relay_url: &str,
+    information: &RelayInformation,
+    reports: &[TestReport],
+    keys: &Keys,
+    timeout: Duration,
+) -> Vec<CaseReport> {
+    let span = span!(Level::INFO, "claims vs reality").entered();
+
+    let mut logger = Logger::new(Nips::Nip11);
+    let advertised = information.supported_nips();
+
+    for report in reports
+        .iter()
+        .filter(|report| advertised.contains(&report.nip.number()))
+    {
+        let claim = format!("supported_nips: {}", report.nip);
+        let mut case = logger.case();
+
+        match report.status() {
+            Status::Passed => {}
+            Status::Inconclusive => {
+                for prefix in report.refusal_prefixes() {
+                    case.log(LogEvent::RefusedClaimCheck {
+                        claim: &claim,
+                        prefix,
+                        message: "some of its tests were refused",
+                    });
+                }
+
+                if report.skipped() {
+                    case.log(LogEvent::Skipped("some of its tests were skipped"));
+                }
+            }
+            Status::Failed => case.log(LogEvent::BrokenClaim {
+                claim: &claim,
+                reality: "its tests failed",
+            }),
+        }
+
+        logger.record_case(claim, case);
+    }
+
+    let limitation = |name| information.limitation(name);
+
+    if let Some(max) = limitation("max_subscriptions").and_then(Value::as_u64) {
+        let claim = format!("max_subscriptions: {max}");
+        let mut case = logger.case();
+        check_max_subscriptions(relay_url, &claim, max, timeout, &mut case).await;
+        logger.record_case(claim, case);
+    }
+
+    if let Some(max) = limitation("max_filters").and_then(Value::as_u64) {
+        let claim = format!("max_filters: {max}");
+        let mut case = logger.case();
+        check_max_filters(relay_url, &claim, max, timeout, &mut case).await;
+        logger.record_case(claim, case);
+    }
+
+    if let Some(max) = limitation("max_limit").and_then(Value::as_u64) {
+        let claim = format!("max_limit: {max}");
+        let mut case = logger.case();
+        check_max_limit(relay_url, &claim, max, keys, timeout, &mut case).await;
+        logger.record_case(claim, case);
+    }
+
+    if let Some(max) = limitation("max_message_length").and_then(Value::as_u64) {
+        let claim = format!("max_message_length: {max}");
+        let mut case = logger.case();
+        check_max_message_length(relay_url, &claim, max, timeout, &mut case).await;
+        logger.record_case(claim, case);
+    }
+
+    if let Some(max) = limitation("max_subid_length").and_then(Value::as_u64) {
+        let claim = format!("max_subid_length: {max}");
+        let mut case = logger.case();
+        check_max_subid_length(relay_url, &claim, max, timeout, &mut case).await;
+        logger.record_case(claim, case);
+    }
+
+    if let Some(difficulty) = limitation("min_pow_difficulty").and_then(Value::as_u64) {
+        if difficulty > 0 {
+            let claim = format!("min_pow_difficulty: {difficulty}");
+            let mut case = logger.case();
+            check_min_pow_difficulty(relay_url, &claim, difficulty, keys, timeout, &mut case).await;
+            logger.record_case(claim, case);
+        }
+    }
+
+    if let Some(required) = limitation("auth_required").and_then(Value::as_bool) {
+        let claim = format!("auth_required: {required}");
+        let mut case = logger.case();
+        check_auth_required(relay_url, &claim, required, timeout, &mut case).await;
+        logger.record_case(claim, case);
+    }
+
+    drop(span);
+
+    TestReport::from(logger).cases
+}
+
+/// Opens as many subscriptions as allowed on a single connection, then one
+/// more, which must be refused. Limits above [`MAX_PROBED_SUBSCRIPTIONS`]
+/// are only checked that far.
+async fn check_max_subscriptions(url: &str, claim: &str, max: u64, timeout: Duration, logger: &mut Logger) {
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    for count in 1..=max.min(MAX_PROBED_SUBSCRIPTIONS) {
+        let id = SubscriptionId::generate();
+        let answer = request(&mut connection, &id, vec![never_matching()], timeout).await;
+
+        if !expect_accepted(claim, &format!("subscription {count}"), answer, logger) {
+            return connection.close().await;
+        }
+    }
+
+    if max > MAX_PROBED_SUBSCRIPTIONS {
+        logger.log(LogEvent::Skipped(&format!(
+            "only {MAX_PROBED_SUBSCRIPTIONS} subscriptions were opened, whether subscription {} is refused is \
+             unverified",
+            max + 1
+        )));
+        return connection.close().await;
+    }
+
+    let id = SubscriptionId::generate();
+    let answer = request(&mut connection, &id, vec![never_matching()], timeout).await;
+
+    expect_refused(claim, &format!("subscription {}", max + 1), answer, logger);
+    connection.close().await;
+}
+
+/// Sends a `REQ` with as many filters as allowed, then one with one more,
+/// which must be refused.
+async fn check_max_filters(url: &str, claim: &str, max: u64, timeout: Duration, logger: &mut Logger) {
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    let filters = |count| (0..count).map(|_| never_matching()).collect();
+
+    let id = SubscriptionId::generate();
+    let answer = request(&mut connection, &id, filters(max), timeout).await;
+
+    if expect_accepted(claim, &format!("a REQ with {max} filters"), answer, logger) {
+        connection.send_logged(json!(["CLOSE", id]).to_string(), logger).await;
+
+        let id = SubscriptionId::generate();
+        let answer = request(&mut connection, &id, filters(max + 1), timeout).await;
+
+        expect_refused(claim, &format!("a REQ with {} filters", max + 1), answer, logger);
+    }
+
+    connection.close().await;
+}
+
+/// Asks for more of the tester's own events than allowed, which must be
+/// clamped rather than refused. Clamping is only verified if the relay
+/// stores more of them than the limit.
+async fn check_max_limit(url: &str, claim: &str, max: u64, keys: &Keys, timeout: Duration, logger: &mut Logger) {
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    let id = SubscriptionId::generate();
+    let filter = json!({ "authors": [keys.public_key()], "limit": max + 1 });
+    let answer = request(&mut connection, &id, vec![filter], timeout).await;
+
+    match answer {
+        Answer::Accepted(events) if events.len() as u64 > max => logger.log(LogEvent::BrokenClaim {
+            claim,
+            reality: &format!("it sent {} events for a limit of {}", events.len(), max + 1),
+        }),
+        Answer::Accepted(events) if (events.len() as u64) < max => logger.log(LogEvent::Skipped(&format!(
+            "the relay only stores {} events by the tester, too few to tell whether it clamps a limit of {}",
+            events.len(),
+            max + 1
+        ))),
+        answer => {
+            expect_accepted(claim, &format!("a limit of {}", max + 1), answer, logger);
+        }
+    }
+
+    connection.close().await;
+}
+
+/// Sends a `REQ` padded with whitespace to exactly the allowed length, then
+/// one byte longer, which must be refused.
+async fn check_max_message_length(url: &str, claim: &str, max: u64, timeout: Duration, logger: &mut Logger) {
+    if max > MAX_PROBED_MESSAGE_LENGTH {
+        return logger.log(LogEvent::Skipped(&format!(
+            "messages longer than {MAX_PROBED_MESSAGE_LENGTH} bytes aren't built, {claim} is unverified"
+        )));
+    }
+
+    let Ok(max) = usize::try_from(max) else {
+        return;
+    };
+
+    let padded = |id: &SubscriptionId, length: usize| {
+        let message = json!(["REQ", id, never_matching()]).to_string();
+        let padding = " ".repeat(length.saturating_sub(message.len()));
+
+        (message.len() <= length).then(|| format!("[{padding}{}", &message[1..]))
+    };
+
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    let id = SubscriptionId::generate();
+
+    let Some(message) = padded(&id, max) else {
+        // Too small a limit for any REQ to fit, which would be a claim of its own
+        return logger.log(LogEvent::BrokenClaim {
+            claim,
+            reality: "no REQ fits within it",
+        });
+    };
+
+    let answer = send_request(&mut connection, message, &id, timeout).await;
+
+    if expect_accepted(claim, &format!("a message of {max} bytes"), answer, logger) {
+        let id = SubscriptionId::generate();
+
+        if let Some(message) = padded(&id, max + 1) {
+            let answer = send_request(&mut connection, message, &id, timeout).await;
+            expect_refused(claim, &format!("a message of {} bytes", max + 1), answer, logger);
+        }
+    }
+
+    connection.close().await;
+}
+
+/// Subscribes with an ID as long as allowed, then one character longer,
+/// which must be refused.
+async fn check_max_subid_length(url: &str, claim: &str, max: u64, timeout: Duration, logger: &mut Logger) {
+    if max > MAX_PROBED_SUBID_LENGTH {
+        return logger.log(LogEvent::Skipped(&format!(
+            "subscription IDs longer than {MAX_PROBED_SUBID_LENGTH} characters aren't built, {claim} is unverified"
+        )));
+    }
+
+    let Ok(max) = usize::try_from(max) else {
+        return;
+    };
+
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    let id = SubscriptionId::new("a".repeat(max));
+    let answer = request(&mut connection, &id, vec![never_matching()], timeout).await;
+
+    if expect_accepted(claim, &format!("a subscription ID of {max} characters"), answer, logger) {
+        connection.send_logged(json!(["CLOSE", id]).to_string(), logger).await;
+
+        let id = SubscriptionId::new("b".repeat(max + 1));
+        let answer = request(&mut connection, &id, vec![never_matching()], timeout).await;
+
+        expect_refused(
+            claim,
+            &format!("a subscription ID of {} characters", max + 1),
+            answer,
+            logger,
+        );
+    }
+
+    connection.close().await;
+}
+
+/// Publishes an event with less proof of work than required, which must be
+/// rejected with a `pow:` prefix.
+async fn check_min_pow_difficulty(
+    url: &str,
+    claim: &str,
+    difficulty: u64,
+    keys: &Keys,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let mut created_at = Timestamp::now();
+
+    // Any event can have enough leading zeroes by chance, an unlucky one would prove nothing
+    let event = loop {
+        let builder = EventBuilder::new_text_note("nostr-relay-tester: claims (min_pow_difficulty)", []);
+        let event = sign_event(keys, builder, created_at, logger);
+
+        match event {
+            Some(event) if u64::from(nip13::get_leading_zero_bits(event.id.inner())) >= difficulty => {
+                created_at = created_at - 1_u64;
+            }
+            Some(event) => break event,
+            None => return,
+        }
+    };
+
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    if !connection
+        .send_logged(json!(["EVENT", event]).to_string(), logger)
+        .await
+    {
+        return;
+    }
+
+    let ok = connection
+        .read_until(claim, timeout, logger, |message, _| {
+            message[0] == "OK" && message[1].as_str() == Some(&event.id.to_hex())
+        })
+        .await;
+
+    if let Some(ok) = ok {
+        let message = ok[3].as_str().unwrap_or_default();
+
+        if ok[2].as_bool() != Some(false) {
+            logger.log(LogEvent::BrokenClaim {
+                claim,
+                reality: "it accepted an event without proof of work",
+            });
+        } else {
+            match Prefix::parse(message) {
+                Ok(Prefix::Pow) => {}
+                Ok(prefix) if prefix.is_policy() => logger.log(LogEvent::RefusedClaimCheck { claim, prefix, message }),
+                _ => logger.log(LogEvent::BrokenClaim {
+                    claim,
+                    reality: &format!(
+                        "it rejected an event without proof of work without a `pow:` prefix: {message:?}"
+                    ),
+                }),
+            }
+        }
+    }
+
+    connection.close().await;
+}
+
+/// Subscribes without authenticating, which must be refused with an
+/// `auth-required:` prefix if and only if authentication is required.
+async fn check_auth_required(url: &str, claim: &str, required: bool, timeout: Duration, logger: &mut Logger) {
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    let id = SubscriptionId::generate();
+    let answer = request(&mut connection, &id, vec![never_matching()], timeout).await;
+    let what = "a REQ without authentication";
+
+    match answer {
+        Answer::Refused(message) if required => {
+            if Prefix::parse(&message).ok() != Some(Prefix::AuthRequired) {
+                logger.log(LogEvent::BrokenClaim {
+                    claim,
+                    reality: &format!("it refused {what} without an `auth-required:` prefix: {message:?}"),
+                });
+            }
+        }
+        answer if required => expect_refused(claim, what, answer, logger),
+        answer => {
+            expect_accepted(claim, what, answer, logger);
+        }
+    }
+
+    connection.close().await;
+}
+
+/// Logs the answer unless it's `Accepted`, as a refusal if the relay gave a
+/// policy reason, and returns whether it was.
+fn expect_accepted(claim: &str, what: &str, answer: Answer, logger: &mut Logger) -> bool {
+    match answer {
+        Answer::Accepted(_) => true,
+        Answer::Refused(message) => {
+            match Prefix::parse(&message) {
+                Ok(prefix) if prefix.is_policy() => logger.log(LogEvent::RefusedClaimCheck {
+                    claim,
+                    prefix,
+                    message: &message,
+                }),
+                _ => logger.log(LogEvent::BrokenClaim {
+                    claim,
+                    reality: &format!("it refused {what}: {message:?}"),
+                }),
+            }
+
+            false
+        }
+        Answer::Silence => {
+            logger.log(LogEvent::BrokenClaim {
+                claim,
+                reality: &format!("it never answered {what}"),
+            });
+
+            false
+        }
+    }
+}
+
+/// Logs the answer unless it's a refusal.
+fn expect_refused(claim: &str, what: &str, answer: Answer, logger: &mut Logger) {
+    let reality = match answer {
+        Answer::Refused(_) => return,
+        Answer::Accepted(_) => format!("it accepted {what}"),
+        Answer::Silence => format!("it never answered {what}"),
+    };
+
+    logger.log(LogEvent::BrokenClaim {
+        claim,
+        reality: &reality,
+    });
+}
+
+async fn request(
+    connection: &mut RawConnection,
+    id: &SubscriptionId,
+    filters: Vec<Value>,
+    timeout: Duration,
+) -> Answer {
+    let mut message = vec![json!("REQ"), json!(id)];
+    message.extend(filters);
+
+    send_request(connection, Value::from(message).to_string(), id, timeout).await
+}
+
+/// Sends a `REQ` and waits for its `EOSE`, or for the relay to refuse it.
+/// Other notices are ignored, they may well be about anything else.
+async fn send_request(
+    connection: &mut RawConnection,
+    message: String,
+    id: &SubscriptionId,
+    timeout: Duration,
+) -> Answer {
+    if let Err(error) = connection.send_text(message).await {
+        return Answer::Refused(format!("dropped the connection ({error})"));
+    }
+
+    let id = id.to_string();
+    let is_ours = |message: &Value| message[1].as_str() == Some(&id);
+
+    let answer = async {
+        let mut events = vec![];
+
+        loop {
+            let message = match connection.recv_frame().await {
+                Some(Ok(Frame::Message(message))) => message,
+                Some(Ok(Frame::Close(_))) | None => return Answer::Refused("closed the connection".to_owned()),
+                Some(Err(error)) => return Answer::Refused(format!("dropped the connection ({error})")),
+                Some(Ok(_)) => continue,
+            };
+
+            match message[0].as_str() {
+                Some("EVENT") if is_ours(&message) => events.push(message[2].clone()),
+                Some("EOSE") if is_ours(&message) => return Answer::Accepted(events),
+                Some("CLOSED") if is_ours(&message) => {
+                    return Answer::Refused(message[2].as_str().unwrap_or_default().to_owned())
+                }
+                // Notices aren't tied to a subscription, only one naming it is taken as an answer
+                Some("NOTICE") if message[1].as_str().is_some_and(|notice| notice.contains(&id)) => {
+                    return Answer::Refused(message[1].as_str().unwrap_or_default().to_owned())
+                }
+                _ => {}
+            }
+        }
+    };
+
+    tokio::time::timeout(timeout, answer).await.unwrap_or(Answer::Silence)
+}
+
+/// A filter no event can match, for subscriptions that are only meant to be
+/// accepted or refused.
+fn never_matching() -> Value {
+    json!({ "ids": ["0".repeat(64)] })
+}
@@ -0,0 +1,235 @@
+use std::{collections::HashSet, time::Duration};
+
+use nostr::{secp256k1::XOnlyPublicKey, Event, EventId, Kind, RelayMessage, SubscriptionId, Tag, Timestamp};
+use nostr_sdk::RelayPoolNotification;
+use tokio::sync::broadcast::{error::RecvError, Receiver};
+
+use crate::tests::{
+    logger::{LogEvent, Logger},
+    prefix::check_prefix,
+};
+
+/// Assertions applied to every event received by [`listen`], along with the
+/// IDs of the events the listener is waiting for.
+#[derive(Clone, Default)]
+pub struct EventCheck {
+    ids: HashSet<EventId>,
+    kind: Option<Kind>,
+    author: Option<XOnlyPublicKey>,
+    since: Option<Timestamp>,
+    tags: Vec<Vec<String>>,
+    exact_tags: Vec<Vec<String>>,
+}
+
+impl EventCheck {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wait for the event with this ID.
+    pub fn id(mut self, id: EventId) -> Self {
+        self.ids.insert(id);
+        self
+    }
+
+    pub fn kind(mut self, kind: Kind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    pub fn author(mut self, author: XOnlyPublicKey) -> Self {
+        self.author = Some(author);
+        self
+    }
+
+    pub fn since(mut self, since: Timestamp) -> Self {
+        self.since = Some(since);
+        self
+    }
+
+    /// Every received event must carry a `[name, value, ...]` tag.
+    pub fn tag<N: Into<String>, V: Into<String>>(mut self, name: N, value: V) -> Self {
+        self.tags.push(vec![name.into(), value.into()]);
+        self
+    }
+
+    /// Every received event must carry this exact tag, with nothing added or
+    /// left out.
+    pub fn exact_tag(mut self, tag: &Tag) -> Self {
+        self.exact_tags.push(tag.as_vec());
+        self
+    }
+
+    fn verify(&self, event: &Event, logger: &mut Logger) {
+        if let Some(expected_kind) = self.kind {
+            if event.kind != expected_kind {
+                logger.log(LogEvent::BadEventKind { expected_kind, event });
+            }
+        }
+
+        if let Some(expected_author) = &self.author {
+            if event.pubkey != *expected_author {
+                logger.log(LogEvent::BadEventAuthor {
+                    expected_author,
+                    author: &event.pubkey,
+                    event,
+                });
+            }
+        }
+
+        if let Some(filter_since_timestamp) = self.since {
+            if event.created_at < filter_since_timestamp {
+                logger.log(LogEvent::BadEventTimestamp {
+                    filter_since_timestamp,
+                    event_timestamp: event.created_at,
+                    event,
+                });
+            }
+        }
+
+        for expected in &self.tags {
+            if !event.tags.iter().any(|tag| tag.as_vec().starts_with(expected)) {
+                logger.log(LogEvent::MissingEventTag { tag: expected, event });
+            }
+        }
+
+        for expected in &self.exact_tags {
+            if !event.tags.iter().any(|tag| tag.as_vec() == *expected) {
+                logger.log(LogEvent::MissingEventTag { tag: expected, event });
+            }
+        }
+    }
+}
+
+/// When [`listen`] should stop listening.
+#[derive(Copy, Clone, PartialEq, Eq)]
+pub enum Until {
+    /// Every event in the check's IDs has been received.
+    ReceivedExpected,
+    /// The relay has sent `EOSE` for the subscription.
+    EndOfStoredEvents,
+}
+
+/// Listens for messages on the given subscription, validating every message
+/// against the subscription ID and every event against `check`. Returns all
+/// events received on the subscription, in order.
+///
+/// Stops when `until` is satisfied, when the relay closes the subscription,
+/// or when `timeout` elapses, in which case [`LogEvent::TimedOut`] is logged.
+pub async fn listen(
+    subscription_name: &str,
+    notifications: &mut Receiver<RelayPoolNotification>,
+    external_subscription_id: &SubscriptionId,
+    check: &EventCheck,
+    until: Until,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Vec<Event> {
+    let mut received = vec![];
+
+    let listener = listen_without_timeout(
+        subscription_name,
+        notifications,
+        external_subscription_id,
+        check,
+        until,
+        &mut received,
+        logger,
+    );
+
+    if tokio::time::timeout(timeout, listener).await.is_err() {
+        logger.log(LogEvent::TimedOut(subscription_name, timeout));
+    }
+
+    received
+}
+
+async fn listen_without_timeout(
+    subscription_name: &str,
+    notifications: &mut Receiver<RelayPoolNotification>,
+    external_subscription_id: &SubscriptionId,
+    check: &EventCheck,
+    until: Until,
+    received: &mut Vec<Event>,
+    logger: &mut Logger,
+) {
+    let mut awaited_ids = check.ids.clone();
+
+    if until == Until::ReceivedExpected && awaited_ids.is_empty() {
+        return;
+    }
+
+    loop {
+        let relay_message = match notifications.recv().await {
+            Ok(RelayPoolNotification::Message { message, .. }) => message,
+            Ok(_) | Err(RecvError::Lagged(_)) => continue,
+            Err(RecvError::Closed) => return,
+        };
+        let relay_message = &relay_message;
+
+        // Check subscription ID for all messages that come with one
+        match relay_message {
+            RelayMessage::Event { subscription_id, .. }
+            | RelayMessage::Closed { subscription_id, .. }
+            | RelayMessage::Count { subscription_id, .. }
+            | RelayMessage::EndOfStoredEvents(subscription_id)
+                if subscription_id.ne(external_subscription_id) =>
+            {
+                // TODO: Check if nostr-sdk lets messages with unknown subscription IDs through.
+                logger.log(LogEvent::UnknownSubscriptionId {
+                    expected_id: external_subscription_id,
+                    id: subscription_id,
+                    message: relay_message,
+                });
+            }
+            _ => {}
+        }
+
+        // Perform checks unique to each message type
+        match relay_message {
+            RelayMessage::Event { subscription_id, event } if subscription_id.eq(external_subscription_id) => {
+                check.verify(event, logger);
+
+                if awaited_ids.remove(&event.id) {
+                    logger.log(LogEvent::ReceivedExpectedEvent(subscription_name, event));
+                }
+
+                received.push(*event.clone());
+
+                if until == Until::ReceivedExpected && awaited_ids.is_empty() {
+                    return;
+                }
+            }
+            // CLOSED for other subscriptions were already logged as unknown above
+            RelayMessage::Closed {
+                subscription_id,
+                message,
+            } if subscription_id.eq(external_subscription_id) => {
+                let prefix = check_prefix("CLOSED", message, logger);
+
+                return logger.log(LogEvent::UnexpectedlyClosedSubscription {
+                    name: subscription_name,
+                    id: external_subscription_id,
+                    prefix,
+                    message,
+                });
+            }
+            RelayMessage::Notice { message } => logger.log(LogEvent::ReceivedNoticeEvent(message)),
+            // OKs for the events being waited on are expected to be queued up from publishing them
+            RelayMessage::Ok { event_id, .. } if !check.ids.contains(event_id) => {
+                logger.log(LogEvent::UnexpectedOkEvent(event_id, relay_message));
+            }
+            RelayMessage::EndOfStoredEvents(subscription_id) => {
+                logger.log(LogEvent::ReceivedEndOfStoredEvents(subscription_id));
+
+                if until == Until::EndOfStoredEvents && subscription_id.eq(external_subscription_id) {
+                    return;
+                }
+            }
+            RelayMessage::Count { subscription_id, count } => {
+                logger.log(LogEvent::UnexpectedCountEvent(subscription_id, *count));
+            }
+            _ => {}
+        }
+    }
+}
@@ -1,24 +1,73 @@
+use std::time::Duration;
+
 use color_eyre::{eyre, eyre::anyhow};
-use nostr::{secp256k1::XOnlyPublicKey, Event, EventId, Kind, RelayMessage, SubscriptionId, Timestamp};
+use nostr::{
+    secp256k1::XOnlyPublicKey,
+    serde_json::{Map, Value},
+    Event, EventId, Kind, RelayMessage, SubscriptionId, Timestamp,
+};
+use tokio_tungstenite::tungstenite::{self, protocol::CloseFrame};
 use tracing::{error, info, warn};
 
 use crate::{
     config::Nips,
-    tests::report::{Errors, TestReport},
+    tests::{
+        nip11::InformationError,
+        prefix::Prefix,
+        report::{CaseReport, Findings, TestReport},
+    },
 };
 
 pub struct Logger {
     nip: Nips,
-    errors: Errors,
+    findings: Findings,
+    cases: Vec<CaseReport>,
+    /// Names of the events logged so far, cases included, for tests to
+    /// assert on.
+    #[cfg(test)]
+    logged: Vec<&'static str>,
 }
 
 impl Logger {
     pub fn new(nip: Nips) -> Logger {
-        Logger { nip, errors: vec![] }
+        Logger {
+            nip,
+            findings: Findings::default(),
+            cases: vec![],
+            #[cfg(test)]
+            logged: vec![],
+        }
+    }
+
+    /// Creates a logger for a named check, to be handed back through
+    /// [`Logger::record_case`] once the check is done.
+    pub fn case(&self) -> Logger {
+        Logger::new(self.nip)
+    }
+
+    /// Stores the outcome of a named check as its own result in the report.
+    pub fn record_case<N: Into<String>>(&mut self, name: N, case: Logger) {
+        self.cases.push(CaseReport {
+            name: name.into(),
+            findings: case.findings,
+        });
+        self.cases.extend(case.cases);
+
+        #[cfg(test)]
+        self.logged.extend(case.logged);
+    }
+
+    #[cfg(test)]
+    pub fn logged(&self) -> &[&'static str] {
+        &self.logged
     }
 
-    /// Sends event to stdout. Stores it afterwards if it's an error.
+    /// Sends event to stdout. Stores it afterwards if it's an error or a
+    /// refusal.
     pub fn log(&mut self, event: LogEvent) {
+        #[cfg(test)]
+        self.logged.push(event.name());
+
         match event {
             LogEvent::EstablishedSubscription(name, id) => {
                 info!("successfully established {name} subscription with id {id}");
@@ -30,10 +79,15 @@ impl Logger {
             LogEvent::FailedToCloseSubscription(name, id, error) => {
                 self.print_and_store_error(anyhow!("failed to close {name} subscription \"{id}\": {error}"));
             }
+            LogEvent::FailedToSignEvent(error) => self.print_and_store_error(anyhow!("failed to sign event: {error}")),
             LogEvent::PublishedEvent(event_id) => info!("successfully published event: {event_id}"),
             LogEvent::FailedToPublishEvent(client_error) => {
                 self.print_and_store_error(anyhow!("failed to publish event: {client_error}"));
             }
+            LogEvent::RejectedEvent(event_id, message) => info!("relay rejected event {event_id}: {message}"),
+            LogEvent::RefusedEvent { id, prefix, message } => {
+                self.print_and_store_error_or_refusal(prefix, anyhow!("relay refused event {id}: {message}"));
+            }
             LogEvent::ReceivedExpectedEvent(name, event) => {
                 info!("received {name} event from subscription: {event:#?}");
             }
@@ -47,9 +101,6 @@ impl Logger {
             LogEvent::UnexpectedCountEvent(subscription_id, count) => self.print_and_store_error(anyhow!(
                 "received unexpected COUNT event for subscription \"{subscription_id}\": {count}"
             )),
-            LogEvent::UnknownSubscriptionClosed(id, message) => {
-                self.print_and_store_error(anyhow!("relay closed unknown subscription \"{id}\": {message}"));
-            }
             LogEvent::UnknownSubscriptionId {
                 expected_id,
                 id,
@@ -76,27 +127,188 @@ impl Logger {
                 event.kind.as_u64(),
                 expected_kind.as_u64(),
             )),
-            LogEvent::UnexpectedlyClosedSubscription { name, id, message } => self.print_and_store_error(anyhow!(
-                "relay closed {name} subscription \"{id}\" unexpectedly: {message}"
+            LogEvent::MissingEventTag { tag, event } => {
+                self.print_and_store_error(anyhow!("received event without a {tag:?} tag: {event:#?}"));
+            }
+            LogEvent::UnexpectedlyClosedSubscription {
+                name,
+                id,
+                prefix,
+                message,
+            } => self.print_and_store_error_or_refusal(
+                prefix,
+                anyhow!("relay closed {name} subscription \"{id}\" unexpectedly: {message}"),
+            ),
+            LogEvent::MissingPrefix { verb, message } => self.print_and_store_error(anyhow!(
+                "relay sent {verb} message without a machine-readable prefix: {message:?}"
+            )),
+            LogEvent::UnknownPrefix { verb, prefix, message } => self.print_and_store_error(anyhow!(
+                "relay sent {verb} message with unknown prefix \"{prefix}:\": {message:?}"
+            )),
+            LogEvent::MissingStoredEvent { name, id } => {
+                self.print_and_store_error(anyhow!("{name} query did not return stored event {id}"));
+            }
+            LogEvent::UnexpectedStoredEvent { name, event } => {
+                self.print_and_store_error(anyhow!("{name} query returned unexpected event: {event:#?}"));
+            }
+            LogEvent::UnexpectedStoredEvents { name, count } => {
+                self.print_and_store_error(anyhow!("{name} query returned {count} unexpected event(s)"));
+            }
+            LogEvent::StoredEphemeralEvent { name, event } => self.print_and_store_error(anyhow!(
+                "{name} query returned ephemeral event {} of kind {}, which relays must only forward to open \
+                 subscriptions instead of storing: {event:#?}",
+                event.id,
+                event.kind.as_u64(),
             )),
+            LogEvent::ServedExpiredEvent { name, id, expiration } => self.print_and_store_error(anyhow!(
+                "{name} query returned event {id}, which expired at {expiration} and must no longer be served"
+            )),
+            LogEvent::MismatchedStoredEvent {
+                id,
+                field,
+                published,
+                stored,
+            } => self.print_and_store_error(anyhow!(
+                "stored event {id} differs from the published one in its `{field}` field, published: {published}, \
+                 stored: {stored}"
+            )),
+            LogEvent::FailedToOpenRawConnection(error) => {
+                self.print_and_store_error(anyhow!("failed to open raw connection to relay: {error}"));
+            }
+            LogEvent::FailedToSendRawMessage(error) => {
+                self.print_and_store_error(anyhow!("failed to send message over raw connection: {error}"));
+            }
+            LogEvent::LostRawConnection(Some(error), _) => {
+                self.print_and_store_error(anyhow!("lost raw connection to relay: {error}"));
+            }
+            LogEvent::LostRawConnection(None, Some(frame)) => self.print_and_store_error(anyhow!(
+                "relay closed raw connection with code {}: {:?}",
+                frame.code,
+                frame.reason
+            )),
+            LogEvent::LostRawConnection(None, None) => {
+                self.print_and_store_error(anyhow!("relay closed raw connection"));
+            }
+            LogEvent::MalformedRelayFrame(text) => {
+                self.print_and_store_error(anyhow!("relay sent a text frame that isn't a JSON array: {text:?}"));
+            }
+            LogEvent::BinaryRelayFrame(length) => {
+                self.print_and_store_error(anyhow!("relay sent a binary frame of {length} bytes"));
+            }
+            LogEvent::RawReply { name, reply } => info!("relay answered {name} with {reply}"),
+            LogEvent::UnexpectedReply { name, expected, reply } => {
+                self.print_and_store_error(anyhow!("relay answered {name} with {reply} instead of {expected}"))
+            }
+            LogEvent::RejectedInvalid { what, message } => info!("relay rejected {what}: {message}"),
+            LogEvent::AcceptedForbidden(what) => self.print_and_store_error(anyhow!("relay accepted {what}")),
+            LogEvent::MissingExpectedPrefix {
+                what,
+                expected,
+                prefix,
+                message,
+            } => self.print_and_store_error_or_refusal(
+                prefix,
+                anyhow!("relay rejected {what}, but its message doesn't start with {expected}: {message:?}"),
+            ),
+            LogEvent::DeliveredInvalidEvent {
+                reason,
+                subscription_id,
+                event,
+            } => self.print_and_store_error(anyhow!(
+                "relay sent event with {reason} on subscription \"{subscription_id}\": {event}"
+            )),
+            LogEvent::FailedToFetchRelayInformation(error) => {
+                self.print_and_store_error(anyhow!("failed to fetch relay information document: {error}"));
+            }
+            LogEvent::FetchedRelayInformation(document) => {
+                let document = Value::from(document.clone());
+                info!("fetched relay information document: {document}");
+            }
+            LogEvent::MissingCorsHeader(header) => {
+                self.print_and_store_error(anyhow!("relay information document served without `{header}` header"));
+            }
+            LogEvent::InvalidInformationField { field, expected, value } => self.print_and_store_error(anyhow!(
+                "relay information document field `{field}` should be {expected}, got: {value}"
+            )),
+            LogEvent::InvalidInformationPubkey { pubkey, reason } => self.print_and_store_error(anyhow!(
+                "relay information document pubkey {pubkey:?} is invalid, {reason}"
+            )),
+            LogEvent::MissingInformationPubkey => info!("relay information document doesn't advertise a pubkey"),
+            LogEvent::BrokenClaim { claim, reality } => {
+                self.print_and_store_error(anyhow!("relay claims `{claim}`, but {reality}"));
+            }
+            LogEvent::RefusedClaimCheck { claim, prefix, message } => self.print_and_store_error_or_refusal(
+                Some(prefix),
+                anyhow!("relay claims `{claim}`, but refused to let it be checked: {message}"),
+            ),
+            LogEvent::Authenticated(pubkey) => info!("successfully authenticated as {pubkey}"),
+            LogEvent::RefusedAuthentication { prefix, message } => {
+                self.print_and_store_error_or_refusal(prefix, anyhow!("relay refused authentication: {message}"));
+            }
+            LogEvent::AuthenticationNotRequired => {
+                info!("relay served the unauthenticated client, authentication isn't required");
+            }
+            LogEvent::ReceivedCount {
+                name,
+                count,
+                approximate,
+            } => info!(
+                "relay counted {count}{} events for {name}",
+                if approximate { " (approximately)" } else { "" }
+            ),
+            LogEvent::MismatchedCount { name, expected, count } => {
+                self.print_and_store_error(anyhow!("relay counted {count} events for {name}, expected {expected}"));
+            }
+            LogEvent::Skipped(reason) => {
+                let reason = anyhow!("{reason}");
+                warn!("skipped: {reason}");
+                self.findings.skipped.push(reason);
+            }
+            LogEvent::MinedEvent {
+                id,
+                difficulty,
+                committed,
+            } => info!("mined event {id} with a difficulty of {difficulty}, committing to {committed}"),
+            LogEvent::RejectedSufficientWork {
+                id,
+                difficulty,
+                committed,
+                target,
+                message,
+            } => self.print_and_store_error(anyhow!(
+                "relay rejected event {id} with a difficulty of {difficulty} committing to {committed} as lacking \
+                 proof of work, despite meeting the target of {target}: {message}"
+            )),
+            LogEvent::TimedOut(name, timeout) => {
+                self.print_and_store_error(anyhow!("timed out after {timeout:?} waiting for {name}"));
+            }
         }
     }
 
     fn print_and_store_error(&mut self, err: eyre::Error) {
         error!("{err}");
-        self.errors.push(err);
+        self.findings.errors.push(err);
+    }
+
+    /// Refusals on policy grounds make the test inconclusive rather than
+    /// failing it.
+    fn print_and_store_error_or_refusal(&mut self, prefix: Option<Prefix>, err: eyre::Error) {
+        match prefix {
+            Some(prefix) if prefix.is_policy() => {
+                warn!("{err}");
+                self.findings.refusals.push((prefix, err));
+            }
+            _ => self.print_and_store_error(err),
+        }
     }
 }
 
 impl From<Logger> for TestReport {
     fn from(value: Logger) -> Self {
-        if value.errors.is_empty() {
-            return TestReport::Passed(value.nip);
-        }
-
-        TestReport::Failed {
+        TestReport {
             nip: value.nip,
-            errors: value.errors,
+            findings: value.findings,
+            cases: value.cases,
         }
     }
 }
@@ -107,14 +319,22 @@ pub enum LogEvent<'a> {
     FailedToEstablishSubscription(&'a str, &'a nostr_sdk::relay::Error),
     ClosedSubscription(&'a str, &'a SubscriptionId),
     FailedToCloseSubscription(&'a str, &'a SubscriptionId, &'a nostr_sdk::relay::Error),
+    FailedToSignEvent(&'a nostr::event::unsigned::Error),
     PublishedEvent(&'a EventId),
-    FailedToPublishEvent(&'a nostr_sdk::client::Error),
+    FailedToPublishEvent(&'a nostr_sdk::relay::Error),
+    /// The relay refused an event we expected it might refuse.
+    RejectedEvent(&'a EventId, &'a str),
+    /// The relay rejected an event it was expected to accept.
+    RefusedEvent {
+        id: &'a EventId,
+        prefix: Option<Prefix>,
+        message: &'a str,
+    },
     ReceivedExpectedEvent(&'a str, &'a Event),
     ReceivedEndOfStoredEvents(&'a SubscriptionId),
     ReceivedNoticeEvent(&'a str),
     UnexpectedOkEvent(&'a EventId, &'a RelayMessage),
     UnexpectedCountEvent(&'a SubscriptionId, usize),
-    UnknownSubscriptionClosed(&'a SubscriptionId, &'a str),
     UnknownSubscriptionId {
         expected_id: &'a SubscriptionId,
         id: &'a SubscriptionId,
@@ -135,9 +355,238 @@ pub enum LogEvent<'a> {
         expected_kind: Kind,
         event: &'a Event,
     },
+    MissingEventTag {
+        tag: &'a [String],
+        event: &'a Event,
+    },
     UnexpectedlyClosedSubscription {
         name: &'a str,
         id: &'a SubscriptionId,
+        prefix: Option<Prefix>,
+        message: &'a str,
+    },
+    /// A rejecting `OK` or a `CLOSED` message doesn't start with a
+    /// machine-readable prefix.
+    MissingPrefix {
+        verb: &'static str,
+        message: &'a str,
+    },
+    /// A rejecting `OK` or a `CLOSED` message starts with a prefix NIP-01
+    /// doesn't define.
+    UnknownPrefix {
+        verb: &'static str,
+        prefix: &'a str,
+        message: &'a str,
+    },
+    /// An event that should be stored was not returned for a query.
+    MissingStoredEvent {
+        name: &'a str,
+        id: &'a EventId,
+    },
+    /// An event that should not be stored (or should not match) was returned
+    /// for a query.
+    UnexpectedStoredEvent {
+        name: &'a str,
+        event: &'a Event,
+    },
+    /// A query returned events it shouldn't have, too many of them to list.
+    UnexpectedStoredEvents {
+        name: &'a str,
+        count: usize,
+    },
+    /// An ephemeral event was returned for a query, meaning the relay stored
+    /// it instead of only forwarding it to open subscriptions.
+    StoredEphemeralEvent {
+        name: &'a str,
+        event: &'a Event,
+    },
+    /// An event was returned for a query after its NIP-40 expiration.
+    ServedExpiredEvent {
+        name: &'a str,
+        id: &'a EventId,
+        expiration: Timestamp,
+    },
+    /// A stored event has the published event's ID, but one of its fields
+    /// differs from what was signed.
+    MismatchedStoredEvent {
+        id: &'a EventId,
+        field: &'static str,
+        published: &'a Value,
+        stored: &'a Value,
+    },
+    FailedToOpenRawConnection(&'a tungstenite::Error),
+    FailedToSendRawMessage(&'a tungstenite::Error),
+    /// The raw connection broke down, or the relay closed it, with a close
+    /// frame or without one.
+    LostRawConnection(Option<&'a tungstenite::Error>, Option<&'a CloseFrame<'a>>),
+    /// The relay sent a text frame that can't be a relay message.
+    MalformedRelayFrame(&'a str),
+    /// The relay sent a binary frame, which NIP-01 has no use for.
+    BinaryRelayFrame(usize),
+    /// How the relay first answered something sent over a raw connection.
+    RawReply {
+        name: &'a str,
+        reply: &'a str,
+    },
+    /// The relay answered something sent over a raw connection, but not the
+    /// way it should have.
+    UnexpectedReply {
+        name: &'a str,
+        expected: &'static str,
+        reply: &'a Value,
+    },
+    /// The relay rejected something deliberately invalid, as it should have.
+    RejectedInvalid {
+        what: &'a str,
+        message: &'a str,
+    },
+    /// The relay accepted something it should have rejected.
+    AcceptedForbidden(&'a str),
+    /// The relay rejected something, but its message doesn't start with the
+    /// prefix it should. A policy prefix makes it a refusal rather than an
+    /// error.
+    MissingExpectedPrefix {
+        what: &'a str,
+        expected: &'static str,
+        prefix: Option<Prefix>,
+        message: &'a str,
+    },
+    /// The relay sent a deliberately corrupted event on a subscription.
+    DeliveredInvalidEvent {
+        reason: &'a str,
+        subscription_id: &'a str,
+        event: &'a Value,
+    },
+    FailedToFetchRelayInformation(&'a InformationError),
+    FetchedRelayInformation(&'a Map<String, Value>),
+    /// One of the CORS headers NIP-11 requires is missing.
+    MissingCorsHeader(&'static str),
+    /// A known field of the relay information document has the wrong shape.
+    InvalidInformationField {
+        field: &'a str,
+        expected: &'static str,
+        value: &'a Value,
+    },
+    InvalidInformationPubkey {
+        pubkey: &'a str,
+        reason: &'a str,
+    },
+    /// The pubkey is optional, so its absence is no error.
+    MissingInformationPubkey,
+    /// The relay doesn't live up to what its relay information document
+    /// claims.
+    BrokenClaim {
+        claim: &'a str,
+        reality: &'a str,
+    },
+    /// Checking a claim of the relay information document took something the
+    /// relay refused on policy grounds.
+    RefusedClaimCheck {
+        claim: &'a str,
+        prefix: Prefix,
         message: &'a str,
     },
+    Authenticated(&'a XOnlyPublicKey),
+    /// The relay rejected a valid authentication event.
+    RefusedAuthentication {
+        prefix: Option<Prefix>,
+        message: &'a str,
+    },
+    AuthenticationNotRequired,
+    ReceivedCount {
+        name: &'a str,
+        count: u64,
+        approximate: bool,
+    },
+    /// The relay answered a `COUNT` with the wrong count, without flagging it
+    /// as approximate.
+    MismatchedCount {
+        name: &'a str,
+        expected: usize,
+        count: u64,
+    },
+    MinedEvent {
+        id: &'a EventId,
+        difficulty: u8,
+        committed: u8,
+    },
+    /// The relay rejected an event with a `pow:` prefix even though it has
+    /// enough proof of work and commits to a high enough target.
+    RejectedSufficientWork {
+        id: &'a EventId,
+        difficulty: u8,
+        committed: u8,
+        target: u8,
+        message: &'a str,
+    },
+    /// A check couldn't be carried out, which leaves the test inconclusive.
+    Skipped(&'a str),
+    /// The relay did not send the awaited message within the configured
+    /// timeout.
+    TimedOut(&'a str, Duration),
+}
+
+#[cfg(test)]
+impl LogEvent<'_> {
+    /// The variant's name, for tests to assert on what was logged.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LogEvent::EstablishedSubscription(..) => "EstablishedSubscription",
+            LogEvent::FailedToEstablishSubscription(..) => "FailedToEstablishSubscription",
+            LogEvent::ClosedSubscription(..) => "ClosedSubscription",
+            LogEvent::FailedToCloseSubscription(..) => "FailedToCloseSubscription",
+            LogEvent::FailedToSignEvent(..) => "FailedToSignEvent",
+            LogEvent::PublishedEvent(..) => "PublishedEvent",
+            LogEvent::FailedToPublishEvent(..) => "FailedToPublishEvent",
+            LogEvent::RejectedEvent(..) => "RejectedEvent",
+            LogEvent::RefusedEvent { .. } => "RefusedEvent",
+            LogEvent::ReceivedExpectedEvent(..) => "ReceivedExpectedEvent",
+            LogEvent::ReceivedEndOfStoredEvents(..) => "ReceivedEndOfStoredEvents",
+            LogEvent::ReceivedNoticeEvent(..) => "ReceivedNoticeEvent",
+            LogEvent::UnexpectedOkEvent(..) => "UnexpectedOkEvent",
+            LogEvent::UnexpectedCountEvent(..) => "UnexpectedCountEvent",
+            LogEvent::UnknownSubscriptionId { .. } => "UnknownSubscriptionId",
+            LogEvent::BadEventAuthor { .. } => "BadEventAuthor",
+            LogEvent::BadEventTimestamp { .. } => "BadEventTimestamp",
+            LogEvent::BadEventKind { .. } => "BadEventKind",
+            LogEvent::MissingEventTag { .. } => "MissingEventTag",
+            LogEvent::UnexpectedlyClosedSubscription { .. } => "UnexpectedlyClosedSubscription",
+            LogEvent::MissingPrefix { .. } => "MissingPrefix",
+            LogEvent::UnknownPrefix { .. } => "UnknownPrefix",
+            LogEvent::MissingStoredEvent { .. } => "MissingStoredEvent",
+            LogEvent::UnexpectedStoredEvent { .. } => "UnexpectedStoredEvent",
+            LogEvent::UnexpectedStoredEvents { .. } => "UnexpectedStoredEvents",
+            LogEvent::StoredEphemeralEvent { .. } => "StoredEphemeralEvent",
+            LogEvent::ServedExpiredEvent { .. } => "ServedExpiredEvent",
+            LogEvent::MismatchedStoredEvent { .. } => "MismatchedStoredEvent",
+            LogEvent::FailedToOpenRawConnection(..) => "FailedToOpenRawConnection",
+            LogEvent::FailedToSendRawMessage(..) => "FailedToSendRawMessage",
+            LogEvent::LostRawConnection(..) => "LostRawConnection",
+            LogEvent::MalformedRelayFrame(..) => "MalformedRelayFrame",
+            LogEvent::BinaryRelayFrame(..) => "BinaryRelayFrame",
+            LogEvent::RawReply { .. } => "RawReply",
+            LogEvent::UnexpectedReply { .. } => "UnexpectedReply",
+            LogEvent::RejectedInvalid { .. } => "RejectedInvalid",
+            LogEvent::AcceptedForbidden(..) => "AcceptedForbidden",
+            LogEvent::MissingExpectedPrefix { .. } => "MissingExpectedPrefix",
+            LogEvent::DeliveredInvalidEvent { .. } => "DeliveredInvalidEvent",
+            LogEvent::FailedToFetchRelayInformation(..) => "FailedToFetchRelayInformation",
+            LogEvent::FetchedRelayInformation(..) => "FetchedRelayInformation",
+            LogEvent::MissingCorsHeader(..) => "MissingCorsHeader",
+            LogEvent::InvalidInformationField { .. } => "InvalidInformationField",
+            LogEvent::InvalidInformationPubkey { .. } => "InvalidInformationPubkey",
+            LogEvent::MissingInformationPubkey => "MissingInformationPubkey",
+            LogEvent::BrokenClaim { .. } => "BrokenClaim",
+            LogEvent::RefusedClaimCheck { .. } => "RefusedClaimCheck",
+            LogEvent::Authenticated(..) => "Authenticated",
+            LogEvent::RefusedAuthentication { .. } => "RefusedAuthentication",
+            LogEvent::AuthenticationNotRequired => "AuthenticationNotRequired",
+            LogEvent::ReceivedCount { .. } => "ReceivedCount",
+            LogEvent::MismatchedCount { .. } => "MismatchedCount",
+            LogEvent::MinedEvent { .. } => "MinedEvent",
+            LogEvent::RejectedSufficientWork { .. } => "RejectedSufficientWork",
+            LogEvent::Skipped(..) => "Skipped",
+            LogEvent::TimedOut(..) => "TimedOut",
+        }
+    }
 }
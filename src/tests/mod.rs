@@ -1,20 +1,41 @@
+mod claims;
+mod listener;
 mod logger;
+mod prefix;
+mod raw;
 pub mod report;
 
 pub mod nip01;
 pub mod nip02;
 pub mod nip09;
+pub mod nip11;
+pub mod nip13;
+pub mod nip22;
+pub mod nip40;
+pub mod nip42;
+pub mod nip45;
+pub mod nip50;
+pub mod nip70;
 
 use color_eyre::eyre;
 
-use crate::{config::Config, tests::report::TestReport};
+use crate::{
+    config::{Config, NipSelection, Nips},
+    tests::{
+        nip11::{Information, InformationError, RelayInformation},
+        report::{Coverage, TestRun},
+    },
+};
 
-pub async fn run(Config { key, nips, relay_url }: Config) -> eyre::Result<Vec<TestReport>> {
+pub async fn run(config: Config) -> eyre::Result<TestRun> {
     use crate::NostrClient;
     use eyre::anyhow;
     use nostr_sdk::client::Options as NostrClientOptions;
 
-    let relay_url = relay_url.ok_or(anyhow!("Relay URL must be specified!"))?;
+    let relay_url = config
+        .relay_url
+        .as_ref()
+        .ok_or(anyhow!("Relay URL must be specified!"))?;
 
     let client = {
         let options = NostrClientOptions::new()
@@ -23,34 +44,127 @@ pub async fn run(Config { key, nips, relay_url }: Config) -> eyre::Result<Vec<Te
             .wait_for_subscription(true)
             .shutdown_on_drop(true);
 
-        NostrClient::with_opts(&key, options)
+        NostrClient::with_opts(&config.key, options)
     };
     client.add_relay(relay_url.as_str()).await?;
     client.connect().await;
 
+    // Fetched once for the whole run: tests relying on it report failures to fetch it
+    let information = if needs_information(&config) {
+        RelayInformation::fetch(relay_url.as_str(), config.timeout_for(Nips::Nip11)).await
+    } else {
+        Err(InformationError::NotFetched)
+    };
+
+    let (nips, coverage) = select_nips(&config, information.as_ref())?;
+
     let relay = client.relay(relay_url.as_str()).await?;
-    let mut results = vec![];
+    let mut reports = vec![];
 
     for nip in nips {
-        results.push(nip.test(&client, &relay).await);
+        reports.push(nip.test(&client, &relay, &config, information.as_ref()).await);
+    }
+
+    // The relay's claims are checked whenever its document is tested or relied on for `auto`
+    let check_claims = config
+        .nips
+        .iter()
+        .any(|&selection| matches!(selection, NipSelection::Auto | NipSelection::Nip(Nips::Nip11)));
+
+    let claims = match &information {
+        Ok(information) if check_claims => {
+            claims::check(
+                relay_url.as_str(),
+                information,
+                &reports,
+                &config.key,
+                config.timeout_for(Nips::Nip11),
+            )
+            .await
+        }
+        _ => vec![],
+    };
+
+    Ok(TestRun {
+        reports,
+        coverage,
+        claims,
+    })
+}
+
+/// Whether any selected test relies on the relay information document, so
+/// that relays with a hanging HTTP endpoint don't slow down the others.
+fn needs_information(config: &Config) -> bool {
+    config.nips.iter().any(|&selection| match selection {
+        NipSelection::Auto | NipSelection::Nip(Nips::Nip11 | Nips::Nip22) => true,
+        NipSelection::Nip(Nips::Nip13) => config.pow_difficulty.is_none(),
+        NipSelection::Nip(_) => false,
+    })
+}
+
+/// Resolves `--nips` into the NIPs to test, in order and without duplicates.
+/// `auto` is replaced with every testable NIP the relay advertises, which
+/// requires its relay information document.
+fn select_nips(config: &Config, information: Information) -> eyre::Result<(Vec<Nips>, Option<Coverage>)> {
+    let mut nips = vec![];
+    let mut coverage = None;
+
+    for &selection in &config.nips {
+        let selected = match selection {
+            NipSelection::Nip(nip) => vec![nip],
+            NipSelection::Auto => {
+                let information = information
+                    .map_err(|error| eyre::anyhow!("`--nips auto` requires the relay information document: {error}"))?;
+
+                let advertised = information.supported_nips();
+
+                coverage = Some(Coverage {
+                    not_covered: advertised
+                        .iter()
+                        .copied()
+                        .filter(|&number| Nips::from_number(number).is_none())
+                        .collect(),
+                    optional_extras: Nips::ALL
+                        .into_iter()
+                        .filter(|nip| !advertised.contains(&nip.number()))
+                        .collect(),
+                });
+
+                Nips::ALL
+                    .into_iter()
+                    .filter(|nip| advertised.contains(&nip.number()))
+                    .collect()
+            }
+        };
+
+        for nip in selected {
+            if !nips.contains(&nip) {
+                nips.push(nip);
+            }
+        }
     }
 
-    Ok(results)
+    Ok((nips, coverage))
 }
 
 mod prelude {
+    use nostr::serde_json::json;
+
     pub use crate::{
         config::Nips,
         tests::{
+            listener::{listen, EventCheck, Until},
             logger::{LogEvent, Logger},
-            report::{Errors, TestReport},
+            prefix::{check_prefix, Prefix},
+            raw::SharedConnection,
+            report::TestReport,
         },
         NostrClient,
     };
-    pub use color_eyre::eyre::anyhow;
-    pub use nostr::{ClientMessage, EventId, Kind, RelayMessage, SubscriptionId, Timestamp};
-    pub use nostr_sdk::{InternalSubscriptionId, Relay, RelayPoolNotification};
+    pub use nostr::{ClientMessage, Event, EventBuilder, EventId, Keys, Kind, SubscriptionId, Timestamp};
+    pub use nostr_sdk::{InternalSubscriptionId, Relay, RelaySendOptions};
     pub use once_cell::sync::Lazy;
+    pub use std::time::Duration;
     pub use tracing::{span, Level};
 
     /// Adds timestamp constraint to the filter and establishes a subscription,
@@ -90,4 +204,294 @@ mod prelude {
             Err(error) => logger.log(LogEvent::FailedToCloseSubscription(name, &id, &error)),
         }
     }
+
+    /// Signs the event with `keys`, using `created_at` instead of the current
+    /// time.
+    pub(super) fn sign_event(
+        keys: &Keys,
+        builder: EventBuilder,
+        created_at: Timestamp,
+        logger: &mut Logger,
+    ) -> Option<Event> {
+        let mut unsigned = builder.to_unsigned_event(keys.public_key());
+        unsigned.created_at = created_at;
+        unsigned.id = EventId::new(
+            &unsigned.pubkey,
+            created_at,
+            &unsigned.kind,
+            &unsigned.tags,
+            &unsigned.content,
+        );
+
+        match unsigned.sign(keys) {
+            Ok(event) => Some(event),
+            Err(error) => {
+                logger.log(LogEvent::FailedToSignEvent(&error));
+                None
+            }
+        }
+    }
+
+    /// Signs and publishes the event, waiting up to `timeout` for the relay's
+    /// `OK`, then makes sure it was stored unaltered unless it's ephemeral.
+    /// Returns the signed event if the relay accepted it.
+    pub(super) async fn publish_event(
+        relay: &Relay,
+        connection: &mut SharedConnection,
+        keys: &Keys,
+        builder: EventBuilder,
+        created_at: Timestamp,
+        timeout: Duration,
+        logger: &mut Logger,
+    ) -> Option<Event> {
+        let event = sign_event(keys, builder, created_at, logger)?;
+
+        match relay.send_event(event.clone(), send_options(timeout)).await {
+            Ok(id) => {
+                logger.log(LogEvent::PublishedEvent(&id));
+
+                if !event.kind.is_ephemeral() {
+                    verify_event_stored(connection, &event, timeout, logger).await;
+                }
+
+                Some(event)
+            }
+            Err(nostr_sdk::relay::Error::EventNotPublished(message)) => {
+                let prefix = check_prefix("OK", &message, logger);
+
+                logger.log(LogEvent::RefusedEvent {
+                    id: &event.id,
+                    prefix,
+                    message: &message,
+                });
+                None
+            }
+            Err(error) => {
+                logger.log(LogEvent::FailedToPublishEvent(&error));
+                None
+            }
+        }
+    }
+
+    /// Publishes an event the relay is free to refuse: a rejection is logged,
+    /// but not as an error. Returns whether the relay accepted the event.
+    pub(super) async fn offer_event(relay: &Relay, event: &Event, timeout: Duration, logger: &mut Logger) -> bool {
+        match relay.send_event(event.clone(), send_options(timeout)).await {
+            Ok(id) => {
+                logger.log(LogEvent::PublishedEvent(&id));
+                true
+            }
+            Err(nostr_sdk::relay::Error::EventNotPublished(message)) => {
+                check_prefix("OK", &message, logger);
+                logger.log(LogEvent::RejectedEvent(&event.id, &message));
+                false
+            }
+            Err(error) => {
+                logger.log(LogEvent::FailedToPublishEvent(&error));
+                false
+            }
+        }
+    }
+
+    pub(super) fn send_options(timeout: Duration) -> RelaySendOptions {
+        RelaySendOptions::new().timeout(Some(timeout))
+    }
+
+    /// Sends a fresh REQ for the filters as-is, collects every stored event
+    /// the relay returns before EOSE and closes the subscription afterwards.
+    pub(super) async fn fetch_stored_events(
+        name: &str,
+        client: &NostrClient,
+        relay: &Relay,
+        filters: Vec<nostr::Filter>,
+        check: &EventCheck,
+        timeout: Duration,
+        logger: &mut Logger,
+    ) -> Option<Vec<Event>> {
+        let id = SubscriptionId::generate();
+        let mut notifications = client.notifications();
+
+        if let Err(error) = relay.send_msg(ClientMessage::new_req(id.clone(), filters), None).await {
+            logger.log(LogEvent::FailedToEstablishSubscription(name, &error));
+            return None;
+        }
+
+        logger.log(LogEvent::EstablishedSubscription(name, &id));
+
+        let events = listen(
+            name,
+            &mut notifications,
+            &id,
+            check,
+            Until::EndOfStoredEvents,
+            timeout,
+            logger,
+        )
+        .await;
+
+        close_subscription(name, relay, id, logger).await;
+
+        Some(events)
+    }
+
+    /// Fetches a published event by ID over a raw connection, ensuring the
+    /// relay returns it before EOSE exactly as published. nostr-sdk would drop
+    /// a stored event whose ID or signature no longer check out before it ever
+    /// got here.
+    pub(super) async fn verify_event_stored(
+        connection: &mut SharedConnection,
+        published: &Event,
+        timeout: Duration,
+        logger: &mut Logger,
+    ) {
+        const NAME: &str = "published event by id";
+        const FIELDS: [&str; 6] = ["pubkey", "created_at", "kind", "tags", "content", "sig"];
+
+        let Some(stored) = connection.query_by_id(NAME, &published.id, timeout, logger).await else {
+            return;
+        };
+
+        let event_id = published.id.to_hex();
+        let (matching, others): (Vec<_>, Vec<_>) =
+            stored.iter().partition(|event| event["id"].as_str() == Some(&event_id));
+
+        if matching.is_empty() {
+            logger.log(LogEvent::MissingStoredEvent {
+                name: NAME,
+                id: &published.id,
+            });
+        }
+
+        if !others.is_empty() {
+            logger.log(LogEvent::UnexpectedStoredEvents {
+                name: NAME,
+                count: others.len(),
+            });
+        }
+
+        let published_json = json!(published);
+
+        for stored in matching {
+            for field in FIELDS
+                .into_iter()
+                .filter(|field| stored[field] != published_json[field])
+            {
+                logger.log(LogEvent::MismatchedStoredEvent {
+                    id: &published.id,
+                    field,
+                    published: &published_json[field],
+                    stored: &stored[field],
+                });
+            }
+        }
+    }
+
+    /// Ensures the stored events returned for a query are exactly the
+    /// expected ones.
+    pub(super) fn verify_stored_events(name: &str, events: &[Event], expected: &[EventId], logger: &mut Logger) {
+        for id in expected {
+            if !events.iter().any(|event| event.id.eq(id)) {
+                logger.log(LogEvent::MissingStoredEvent { name, id });
+            }
+        }
+
+        for event in events {
+            if !expected.contains(&event.id) {
+                logger.log(LogEvent::UnexpectedStoredEvent { name, event });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod unit_tests {
+    use nostr::serde_json::json;
+
+    use super::*;
+    use crate::config::NostrKeys;
+
+    fn config(nips: &[NipSelection]) -> Config {
+        Config {
+            relay_url: None,
+            key: NostrKeys::default(),
+            nips: nips.to_vec(),
+            timeout: 1,
+            nip_timeouts: vec![],
+            pow_difficulty: None,
+        }
+    }
+
+    fn advertising(nips: &[u16]) -> RelayInformation {
+        RelayInformation::from_document(json!({ "supported_nips": nips }))
+    }
+
+    #[test]
+    fn explicit_nips_keep_their_order() {
+        let config = config(&[
+            NipSelection::Nip(Nips::Nip09),
+            NipSelection::Nip(Nips::Nip01),
+            NipSelection::Nip(Nips::Nip09),
+        ]);
+
+        let (nips, coverage) = select_nips(&config, Err(&InformationError::NotFetched)).unwrap();
+
+        assert_eq!(nips, [Nips::Nip09, Nips::Nip01]);
+        assert!(coverage.is_none());
+    }
+
+    #[test]
+    fn auto_merges_with_explicit_nips() {
+        let config = config(&[
+            NipSelection::Nip(Nips::Nip40),
+            NipSelection::Auto,
+            NipSelection::Nip(Nips::Nip01),
+        ]);
+        let information = advertising(&[1, 11, 40]);
+
+        let (nips, _) = select_nips(&config, Ok(&information)).unwrap();
+
+        assert_eq!(nips, [Nips::Nip40, Nips::Nip01, Nips::Nip11]);
+    }
+
+    #[test]
+    fn auto_reports_coverage() {
+        let information = advertising(&[1, 4, 11, 96]);
+
+        let (nips, coverage) = select_nips(&config(&[NipSelection::Auto]), Ok(&information)).unwrap();
+        let coverage = coverage.unwrap();
+
+        assert_eq!(nips, [Nips::Nip01, Nips::Nip11]);
+        assert_eq!(coverage.not_covered, [4, 96]);
+        assert_eq!(
+            coverage.optional_extras,
+            Nips::ALL
+                .into_iter()
+                .filter(|nip| !nips.contains(nip))
+                .collect::<Vec<_>>()
+        );
+    }
+
+    #[test]
+    fn auto_requires_the_document() {
+        let Err(error) = select_nips(&config(&[NipSelection::Auto]), Err(&InformationError::NotFetched)) else {
+            panic!("auto selected NIPs without the document");
+        };
+
+        assert!(error.to_string().contains("`--nips auto`"), "{error}");
+    }
+
+    #[test]
+    fn document_only_fetched_when_needed() {
+        let nip = |nip| config(&[NipSelection::Nip(Nips::Nip01), NipSelection::Nip(nip)]);
+
+        assert!(!needs_information(&nip(Nips::Nip09)));
+        assert!(needs_information(&nip(Nips::Nip11)));
+        assert!(needs_information(&nip(Nips::Nip22)));
+        assert!(needs_information(&nip(Nips::Nip13)));
+        assert!(needs_information(&config(&[NipSelection::Auto])));
+
+        let mut pow = nip(Nips::Nip13);
+        pow.pow_difficulty = Some(8);
+        assert!(!needs_information(&pow));
+    }
 }
@@ -1,55 +1,68 @@
-use nostr::secp256k1::XOnlyPublicKey;
-use nostr_sdk::RelayPoolNotification;
-use tokio::sync::broadcast::Receiver;
+use nostr::Metadata;
 
 use crate::tests::prelude::*;
 
+mod addressable;
+mod ephemeral;
+mod filters;
+mod invalid;
+mod malformed;
+mod replaceable;
+mod wire;
+
 const SUBSCRIPTION_NAME: &str = "new events";
+const METADATA_QUERY_NAME: &str = "stored metadata";
 
 static PUBLISH_TEST_INTERNAL_SUBSCRIPTION_ID: Lazy<InternalSubscriptionId> =
     Lazy::new(|| InternalSubscriptionId::Custom("publish_test".to_owned()));
 
-pub async fn test(client: &NostrClient, relay: &Relay) -> TestReport {
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
     let span = span!(Level::INFO, "nip01: publishing event").entered();
 
     let mut logger = Logger::new(Nips::Nip01);
+    let mut connection = SharedConnection::new(relay.url().as_str());
+    let keys = client.keys().await;
+    let pubkey = keys.public_key();
 
     let event_subscription: Option<(SubscriptionId, Timestamp)> = establish_subscription(
         SUBSCRIPTION_NAME,
         relay,
         PUBLISH_TEST_INTERNAL_SUBSCRIPTION_ID.clone(),
-        nostr::Filter::new()
-            .author(client.keys().await.public_key())
-            .kind(Kind::TextNote),
+        nostr::Filter::new().author(pubkey).kind(Kind::TextNote),
         &mut logger,
     )
     .await;
 
-    let published_id = match client.publish_text_note("nostr-relay-tester: nip01", []).await {
-        Ok(id) => {
-            logger.log(LogEvent::PublishedEvent(&id));
-            Some(id)
-        }
-        Err(error) => {
-            logger.log(LogEvent::FailedToPublishEvent(&error));
-            None
-        }
-    };
+    // Subscribe to notifications before publishing so the echoed event can't slip past us
+    let mut notifications = client.notifications();
+
+    let published = publish_event(
+        relay,
+        &mut connection,
+        &keys,
+        EventBuilder::new_text_note("nostr-relay-tester: nip01", []),
+        Timestamp::now(),
+        timeout,
+        &mut logger,
+    )
+    .await;
 
     if let Some((id, timestamp)) = event_subscription {
-        if let Some(event_id) = published_id {
-            test_receive_published_note_from_subscription(
+        if let Some(event) = &published {
+            listen(
                 SUBSCRIPTION_NAME,
-                client.notifications(),
-                event_id,
-                id.clone(),
-                client.keys().await.public_key(),
-                timestamp,
+                &mut notifications,
+                &id,
+                &EventCheck::new()
+                    .id(event.id)
+                    .kind(Kind::TextNote)
+                    .author(pubkey)
+                    .since(timestamp),
+                Until::ReceivedExpected,
+                timeout,
                 &mut logger,
             )
             .await;
-
-            // TODO: Fetch event from relay
         }
 
         close_subscription(SUBSCRIPTION_NAME, relay, id, &mut logger).await;
@@ -57,110 +70,139 @@ pub async fn test(client: &NostrClient, relay: &Relay) -> TestReport {
 
     drop(span);
 
-    // TODO: Set metadata
     let span = span!(Level::INFO, "nip01: set metadata").entered();
 
+    test_set_metadata(client, relay, &mut connection, &keys, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: filters").entered();
+
+    filters::test(client, relay, &mut connection, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: replaceable events").entered();
+
+    replaceable::test(client, relay, &mut connection, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: addressable events").entered();
+
+    addressable::test(client, relay, &mut connection, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: ephemeral events").entered();
+
+    ephemeral::test(client, relay, &mut connection, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: invalid events").entered();
+
+    invalid::test(client, relay, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: wire frames").entered();
+
+    wire::test(client, relay, timeout, &mut logger).await;
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip01: malformed messages").entered();
+
+    malformed::test(relay, timeout, &mut logger).await;
+
+    connection.close().await;
+
     drop(span);
 
     TestReport::from(logger)
 }
 
-// TODO: Extract this into a listener function that takes in a desired check
-// TODO: Some kind of timeout in case the relay never sends the desired event
-async fn test_receive_published_note_from_subscription(
-    subscription_name: &str,
-    mut notifications_stream: Receiver<RelayPoolNotification>,
-    published_event_id: EventId,
-    external_subscription_id: SubscriptionId,
-    pubkey: XOnlyPublicKey,
-    filter_since_timestamp: Timestamp,
+/// Publishes successive versions of the key's metadata, ensuring only the
+/// newest one is ever served and that an older version can't replace it.
+async fn test_set_metadata(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    timeout: Duration,
     logger: &mut Logger,
 ) {
-    while let Ok(notification) = notifications_stream.recv().await {
-        if let RelayPoolNotification::Message {
-            message: relay_message, ..
-        } = notification
-        {
-            let relay_message = &relay_message;
-
-            // Check subscription ID for all messages that come with one
-            match relay_message {
-                RelayMessage::Event { subscription_id, .. }
-                | RelayMessage::Closed { subscription_id, .. }
-                | RelayMessage::Count { subscription_id, .. }
-                | RelayMessage::EndOfStoredEvents(subscription_id) => {
-                    // TODO: Check if nostr-sdk lets messages with unknown subscription IDs through.
-                    if !subscription_id.eq(&external_subscription_id) {
-                        logger.log(LogEvent::UnknownSubscriptionId {
-                            expected_id: &external_subscription_id,
-                            id: subscription_id,
-                            message: relay_message,
-                        });
-                    }
-                }
-                _ => {}
-            }
-
-            // Perform checks unique to each message type
-            match relay_message {
-                RelayMessage::Event { event, .. } => {
-                    if published_event_id.eq(&event.id) {
-                        return logger.log(LogEvent::ReceivedExpectedEvent("published", event));
-                    }
-
-                    if event.kind != Kind::TextNote {
-                        logger.log(LogEvent::BadEventKind {
-                            expected_kind: Kind::TextNote,
-                            event,
-                        });
-                    }
-
-                    if event.pubkey != pubkey {
-                        logger.log(LogEvent::BadEventAuthor {
-                            expected_author: &pubkey,
-                            author: &event.pubkey,
-                            event,
-                        });
-                    }
-
-                    if event.created_at < filter_since_timestamp {
-                        logger.log(LogEvent::BadEventTimestamp {
-                            filter_since_timestamp,
-                            event_timestamp: event.created_at,
-                            event,
-                        });
-                    }
-                }
-                RelayMessage::Closed {
-                    subscription_id,
-                    message,
-                } => {
-                    if subscription_id.eq(&external_subscription_id) {
-                        return logger.log(LogEvent::UnexpectedlyClosedSubscription {
-                            name: subscription_name,
-                            id: &external_subscription_id,
-                            message,
-                        });
-                    }
-
-                    logger.log(LogEvent::UnknownSubscriptionClosed(subscription_id, message));
-                }
-                RelayMessage::Notice { message } => logger.log(LogEvent::ReceivedNoticeEvent(message)),
-                RelayMessage::Ok { event_id, .. } => {
-                    // Check should be redundant, but it's placed here in case it's not.
-                    if published_event_id.ne(event_id) {
-                        logger.log(LogEvent::UnexpectedOkEvent(event_id, relay_message));
-                    }
-                }
-
-                RelayMessage::EndOfStoredEvents(subscription_id) => {
-                    logger.log(LogEvent::ReceivedEndOfStoredEvents(subscription_id));
-                }
-                RelayMessage::Count { subscription_id, count } => {
-                    logger.log(LogEvent::UnexpectedCountEvent(subscription_id, *count));
-                }
-                _ => {}
-            }
-        }
+    let metadata = |version: &str| {
+        EventBuilder::set_metadata(
+            &Metadata::new()
+                .name("nostr-relay-tester")
+                .about(format!("nostr-relay-tester: nip01 metadata ({version})")),
+        )
+    };
+
+    let Some(first) = publish_event(
+        relay,
+        connection,
+        keys,
+        metadata("first"),
+        Timestamp::now(),
+        timeout,
+        logger,
+    )
+    .await
+    else {
+        return;
+    };
+
+    fetch_and_verify_metadata(client, relay, keys, first.id, timeout, logger).await;
+
+    let Some(second) = publish_event(
+        relay,
+        connection,
+        keys,
+        metadata("second"),
+        first.created_at + 1_u64,
+        timeout,
+        logger,
+    )
+    .await
+    else {
+        return;
+    };
+
+    fetch_and_verify_metadata(client, relay, keys, second.id, timeout, logger).await;
+
+    // Relays may either refuse the outdated version or silently discard it
+    if let Some(outdated) = sign_event(keys, metadata("outdated"), first.created_at - 10_u64, logger) {
+        offer_event(relay, &outdated, timeout, logger).await;
+        fetch_and_verify_metadata(client, relay, keys, second.id, timeout, logger).await;
+    }
+}
+
+async fn fetch_and_verify_metadata(
+    client: &NostrClient,
+    relay: &Relay,
+    keys: &Keys,
+    expected_id: EventId,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let stored = fetch_stored_events(
+        METADATA_QUERY_NAME,
+        client,
+        relay,
+        vec![nostr::Filter::new().author(keys.public_key()).kind(Kind::Metadata)],
+        &EventCheck::new()
+            .id(expected_id)
+            .kind(Kind::Metadata)
+            .author(keys.public_key()),
+        timeout,
+        logger,
+    )
+    .await;
+
+    if let Some(events) = stored {
+        verify_stored_events(METADATA_QUERY_NAME, &events, &[expected_id], logger);
     }
 }
@@ -0,0 +1,291 @@
+use nostr::{Filter, Tag};
+
+use crate::tests::prelude::*;
+
+const QUERY_NAME: &str = "stored addressable events";
+
+/// Kind of every addressable event published by these checks.
+const ADDRESSABLE_KIND: u64 = 30_444;
+
+/// Publishes addressable events under different `d` tags and ensures they're
+/// replaced per (kind, pubkey, d) and can be deleted by their coordinate.
+pub(super) async fn test(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let keys = Keys::generate();
+    let base = Timestamp::now() - 10_u64;
+
+    let mut case = logger.case();
+    let distinct = test_distinct_identifiers(client, relay, connection, &keys, base, timeout, &mut case).await;
+    logger.record_case("addressable: distinct d tags", case);
+
+    let Some((first_a, b)) = distinct else {
+        return;
+    };
+
+    let mut case = logger.case();
+    let a = test_same_identifier(client, relay, connection, &keys, &first_a, &b, timeout, &mut case).await;
+    logger.record_case("addressable: same d tag replaces", case);
+
+    let Some(a) = a else {
+        return;
+    };
+
+    let mut case = logger.case();
+    let empty =
+        test_missing_and_empty_identifier(client, relay, connection, &keys, base, [&a, &b], timeout, &mut case).await;
+    logger.record_case("addressable: missing and empty d tags", case);
+
+    let Some(empty) = empty else {
+        return;
+    };
+
+    let mut case = logger.case();
+    test_deletion_by_coordinate(client, relay, connection, &keys, &a, [&b, &empty], timeout, &mut case).await;
+    logger.record_case("addressable: deletion by a tag", case);
+}
+
+/// Publishes events under the `a` and `b` addresses, which must both be
+/// stored and each be the only result of a `#d` query for its address.
+async fn test_distinct_identifiers(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    created_at: Timestamp,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Option<(Event, Event)> {
+    let a = publish_event(
+        relay,
+        connection,
+        keys,
+        version(Some("a"), "a"),
+        created_at,
+        timeout,
+        logger,
+    )
+    .await;
+    let b = publish_event(
+        relay,
+        connection,
+        keys,
+        version(Some("b"), "b"),
+        created_at,
+        timeout,
+        logger,
+    )
+    .await;
+
+    let (a, b) = (a?, b?);
+
+    fetch_and_verify(client, relay, keys, None, &[a.id, b.id], timeout, logger).await;
+    fetch_and_verify(client, relay, keys, Some("a"), &[a.id], timeout, logger).await;
+    fetch_and_verify(client, relay, keys, Some("b"), &[b.id], timeout, logger).await;
+
+    Some((a, b))
+}
+
+/// Publishes a newer event under the `a` address, which must replace the
+/// previous one while leaving the `b` address alone.
+#[allow(clippy::too_many_arguments)]
+async fn test_same_identifier(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    previous: &Event,
+    untouched: &Event,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Option<Event> {
+    let replacement = publish_event(
+        relay,
+        connection,
+        keys,
+        version(Some("a"), "a, replacement"),
+        previous.created_at + 1_u64,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    fetch_and_verify(client, relay, keys, Some("a"), &[replacement.id], timeout, logger).await;
+    fetch_and_verify(
+        client,
+        relay,
+        keys,
+        None,
+        &[replacement.id, untouched.id],
+        timeout,
+        logger,
+    )
+    .await;
+
+    Some(replacement)
+}
+
+/// Publishes an event without a `d` tag, then a newer one with an empty `d`
+/// tag. Both share the empty address, so the latter must replace the former.
+#[allow(clippy::too_many_arguments)]
+async fn test_missing_and_empty_identifier(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    created_at: Timestamp,
+    others: [&Event; 2],
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Option<Event> {
+    let others = others.map(|event| event.id);
+
+    let missing = publish_event(
+        relay,
+        connection,
+        keys,
+        version(None, "missing"),
+        created_at,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    fetch_and_verify(
+        client,
+        relay,
+        keys,
+        None,
+        &[others[0], others[1], missing.id],
+        timeout,
+        logger,
+    )
+    .await;
+
+    let empty = publish_event(
+        relay,
+        connection,
+        keys,
+        version(Some(""), "empty"),
+        created_at + 1_u64,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    fetch_and_verify(
+        client,
+        relay,
+        keys,
+        None,
+        &[others[0], others[1], empty.id],
+        timeout,
+        logger,
+    )
+    .await;
+
+    Some(empty)
+}
+
+/// Deletes the `a` address through its `kind:pubkey:d` coordinate, which must
+/// remove it without touching any other address.
+#[allow(clippy::too_many_arguments)]
+async fn test_deletion_by_coordinate(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    target: &Event,
+    others: [&Event; 2],
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let coordinate = Tag::A {
+        kind: target.kind,
+        public_key: keys.public_key(),
+        identifier: "a".to_owned(),
+        relay_url: None,
+    };
+
+    let deletion = publish_event(
+        relay,
+        connection,
+        keys,
+        EventBuilder::new(Kind::EventDeletion, "", [coordinate]),
+        target.created_at + 1_u64,
+        timeout,
+        logger,
+    )
+    .await;
+
+    if deletion.is_none() {
+        return;
+    }
+
+    fetch_and_verify(client, relay, keys, Some("a"), &[], timeout, logger).await;
+    fetch_and_verify(
+        client,
+        relay,
+        keys,
+        None,
+        &others.map(|event| event.id),
+        timeout,
+        logger,
+    )
+    .await;
+}
+
+/// Fetches the author's addressable events, restricted to a single address
+/// through a `#d` filter if one is given, and ensures they're exactly the
+/// expected ones.
+async fn fetch_and_verify(
+    client: &NostrClient,
+    relay: &Relay,
+    keys: &Keys,
+    identifier: Option<&str>,
+    expected: &[EventId],
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let filter = Filter::new()
+        .author(keys.public_key())
+        .kind(Kind::from(ADDRESSABLE_KIND));
+
+    let (name, filter) = match identifier {
+        Some(identifier) => (
+            format!("{QUERY_NAME} (#d {identifier:?})"),
+            filter.identifier(identifier),
+        ),
+        None => (QUERY_NAME.to_owned(), filter),
+    };
+
+    let stored = fetch_stored_events(
+        &name,
+        client,
+        relay,
+        vec![filter],
+        &EventCheck::new()
+            .kind(Kind::from(ADDRESSABLE_KIND))
+            .author(keys.public_key()),
+        timeout,
+        logger,
+    )
+    .await;
+
+    if let Some(events) = stored {
+        verify_stored_events(&name, &events, expected, logger);
+    }
+}
+
+/// An addressable event with the given `d` tag, or none at all.
+fn version(identifier: Option<&str>, label: &str) -> EventBuilder {
+    EventBuilder::new(
+        Kind::from(ADDRESSABLE_KIND),
+        format!("nostr-relay-tester: nip01 addressable ({label})"),
+        identifier.map(|identifier| Tag::Identifier(identifier.to_owned())),
+    )
+}
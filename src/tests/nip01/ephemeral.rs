@@ -0,0 +1,100 @@
+use nostr::Filter;
+
+use crate::tests::prelude::*;
+
+const SUBSCRIPTION_NAME: &str = "new ephemeral events";
+const QUERY_NAME: &str = "stored ephemeral event";
+
+static EPHEMERAL_TEST_INTERNAL_SUBSCRIPTION_ID: Lazy<InternalSubscriptionId> =
+    Lazy::new(|| InternalSubscriptionId::Custom("ephemeral_test".to_owned()));
+
+/// Kind of the ephemeral event published by these checks.
+const EPHEMERAL_KIND: u64 = 20_444;
+
+/// Publishes an ephemeral event, which must reach open subscriptions but never
+/// be returned for stored events afterwards.
+pub(super) async fn test(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let keys = client.keys().await;
+    let pubkey = keys.public_key();
+    let kind = Kind::from(EPHEMERAL_KIND);
+
+    let mut case = logger.case();
+
+    let event_subscription: Option<(SubscriptionId, Timestamp)> = establish_subscription(
+        SUBSCRIPTION_NAME,
+        relay,
+        EPHEMERAL_TEST_INTERNAL_SUBSCRIPTION_ID.clone(),
+        Filter::new().author(pubkey).kind(kind),
+        &mut case,
+    )
+    .await;
+
+    // Subscribe to notifications before publishing, the relay won't send the event again
+    let mut notifications = client.notifications();
+
+    let published = publish_event(
+        relay,
+        connection,
+        &keys,
+        EventBuilder::new(kind, "nostr-relay-tester: nip01 ephemeral", []),
+        Timestamp::now(),
+        timeout,
+        &mut case,
+    )
+    .await;
+
+    if let Some((id, timestamp)) = event_subscription {
+        if let Some(event) = &published {
+            listen(
+                SUBSCRIPTION_NAME,
+                &mut notifications,
+                &id,
+                &EventCheck::new()
+                    .id(event.id)
+                    .kind(kind)
+                    .author(pubkey)
+                    .since(timestamp),
+                Until::ReceivedExpected,
+                timeout,
+                &mut case,
+            )
+            .await;
+        }
+
+        close_subscription(SUBSCRIPTION_NAME, relay, id, &mut case).await;
+    }
+
+    logger.record_case("ephemeral: delivered to subscriptions", case);
+
+    let Some(published) = published else {
+        return;
+    };
+
+    let mut case = logger.case();
+
+    let stored = fetch_stored_events(
+        QUERY_NAME,
+        client,
+        relay,
+        vec![Filter::new().id(published.id)],
+        &EventCheck::new(),
+        timeout,
+        &mut case,
+    )
+    .await;
+
+    for event in stored.iter().flatten() {
+        case.log(LogEvent::StoredEphemeralEvent {
+            name: QUERY_NAME,
+            event,
+        });
+    }
+
+    logger.record_case("ephemeral: not stored", case);
+}
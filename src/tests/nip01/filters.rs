@@ -0,0 +1,233 @@
+use nostr::{secp256k1::XOnlyPublicKey, Alphabet, Filter, Tag, TagKind};
+
+use crate::tests::prelude::*;
+
+/// Kind of the corpus event that carries the arbitrary single-letter tag.
+const LABELLED_KIND: u64 = 4444;
+
+/// Seeds a corpus of events and runs one REQ per filter case against it,
+/// expecting exactly the matching events back.
+pub(super) async fn test(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let alice = Keys::generate();
+    let bob = Keys::generate();
+
+    let Some(corpus) = seed_corpus(relay, connection, &alice, &bob, timeout, logger).await else {
+        return;
+    };
+    let [note, reply, mention, reaction, labelled] = &corpus;
+
+    let (alice, bob) = (alice.public_key(), bob.public_key());
+    let both_authors = || Filter::new().authors([alice, bob]);
+
+    let cases = [
+        (
+            "ids",
+            vec![Filter::new().ids([note.id, mention.id])],
+            vec![note, mention],
+        ),
+        (
+            "multiple authors",
+            vec![both_authors()],
+            vec![note, reply, mention, reaction, labelled],
+        ),
+        (
+            "multiple kinds",
+            vec![both_authors().kinds([Kind::TextNote, Kind::Reaction])],
+            vec![note, reply, mention, reaction],
+        ),
+        (
+            "author and kind",
+            vec![Filter::new().author(bob).kind(Kind::TextNote)],
+            vec![mention],
+        ),
+        ("#e tag", vec![Filter::new().event(note.id)], vec![reply]),
+        ("#p tag", vec![Filter::new().pubkey(alice)], vec![mention, reaction]),
+        (
+            "arbitrary tag",
+            vec![Filter::new().custom_tag(Alphabet::Z, [label(&alice)])],
+            vec![labelled],
+        ),
+        (
+            "since (inclusive)",
+            vec![both_authors().since(mention.created_at)],
+            vec![mention, reaction, labelled],
+        ),
+        (
+            "until (inclusive)",
+            vec![both_authors().until(mention.created_at)],
+            vec![note, reply, mention],
+        ),
+        (
+            "since and until",
+            vec![both_authors().since(reply.created_at).until(reaction.created_at)],
+            vec![reply, mention, reaction],
+        ),
+        // Relays must return the newest events first when limiting
+        ("limit", vec![both_authors().limit(2)], vec![reaction, labelled]),
+        (
+            "multiple filters",
+            vec![
+                Filter::new().id(note.id),
+                Filter::new().author(bob).kind(Kind::Reaction),
+                Filter::new().custom_tag(Alphabet::Z, [label(&alice)]),
+            ],
+            vec![note, reaction, labelled],
+        ),
+    ];
+
+    for (name, filters, expected) in cases {
+        let expected: Vec<EventId> = expected.into_iter().map(|event| event.id).collect();
+
+        test_filter(name, client, relay, filters, &expected, timeout, logger).await;
+    }
+}
+
+/// Publishes the corpus the filter cases run against, ten seconds apart and
+/// in the past, returning it in publishing order:
+///
+/// 1. note: alice, text note
+/// 2. reply: alice, text note tagging the note and bob
+/// 3. mention: bob, text note tagging alice
+/// 4. reaction: bob, reaction tagging the reply and alice
+/// 5. labelled: alice, regular event carrying a `z` tag
+async fn seed_corpus(
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    alice: &Keys,
+    bob: &Keys,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Option<[Event; 5]> {
+    let base = Timestamp::now() - 60_u64;
+
+    let note = publish_event(
+        relay,
+        connection,
+        alice,
+        EventBuilder::new_text_note("nostr-relay-tester: nip01 filters (note)", []),
+        base,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    let reply = publish_event(
+        relay,
+        connection,
+        alice,
+        EventBuilder::new_text_note(
+            "nostr-relay-tester: nip01 filters (reply)",
+            [event_tag(note.id), pubkey_tag(bob.public_key())],
+        ),
+        base + 10_u64,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    let mention = publish_event(
+        relay,
+        connection,
+        bob,
+        EventBuilder::new_text_note(
+            "nostr-relay-tester: nip01 filters (mention)",
+            [pubkey_tag(alice.public_key())],
+        ),
+        base + 20_u64,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    let reaction = publish_event(
+        relay,
+        connection,
+        bob,
+        EventBuilder::new(
+            Kind::Reaction,
+            "+",
+            [event_tag(reply.id), pubkey_tag(alice.public_key())],
+        ),
+        base + 30_u64,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    let labelled = publish_event(
+        relay,
+        connection,
+        alice,
+        EventBuilder::new(
+            Kind::from(LABELLED_KIND),
+            "nostr-relay-tester: nip01 filters (labelled)",
+            [Tag::Generic(
+                TagKind::Custom(Alphabet::Z.to_string()),
+                vec![label(&alice.public_key())],
+            )],
+        ),
+        base + 40_u64,
+        timeout,
+        logger,
+    )
+    .await?;
+
+    Some([note, reply, mention, reaction, labelled])
+}
+
+async fn test_filter(
+    name: &str,
+    client: &NostrClient,
+    relay: &Relay,
+    filters: Vec<Filter>,
+    expected: &[EventId],
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let query_name = format!("filter: {name}");
+    let mut case = logger.case();
+
+    let stored = fetch_stored_events(
+        &query_name,
+        client,
+        relay,
+        filters,
+        &EventCheck::new(),
+        timeout,
+        &mut case,
+    )
+    .await;
+
+    if let Some(events) = stored {
+        verify_stored_events(&query_name, &events, expected, &mut case);
+    }
+
+    logger.record_case(query_name, case);
+}
+
+/// Value of the `z` tag, unique to this run's corpus.
+fn label(alice: &XOnlyPublicKey) -> String {
+    format!("nostr-relay-tester:{alice}")
+}
+
+fn event_tag(event_id: EventId) -> Tag {
+    Tag::Event {
+        event_id,
+        relay_url: None,
+        marker: None,
+    }
+}
+
+fn pubkey_tag(public_key: XOnlyPublicKey) -> Tag {
+    Tag::PublicKey {
+        public_key,
+        relay_url: None,
+        alias: None,
+    }
+}
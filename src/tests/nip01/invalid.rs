@@ -0,0 +1,236 @@
+use nostr::{
+    serde_json::{json, Value},
+    JsonUtil,
+};
+
+use crate::tests::{
+    prelude::*,
+    raw::{is_end_of_stored_events, RawConnection},
+};
+
+const SUBSCRIPTION_NAME: &str = "new invalid events";
+const QUERY_NAME: &str = "stored invalid event";
+
+/// An event corrupted after signing, as it's sent to the relay.
+struct Corruption {
+    reason: &'static str,
+    event: Value,
+}
+
+impl Corruption {
+    fn id(&self) -> &str {
+        self.event["id"].as_str().unwrap_or_default()
+    }
+}
+
+/// Sends deliberately corrupted events over a raw connection, since the SDK
+/// only ever sends events it signed correctly. Each one must be refused with
+/// an `invalid:` prefix and never be sent back on a subscription.
+pub(super) async fn test(client: &NostrClient, relay: &Relay, timeout: Duration, logger: &mut Logger) {
+    let keys = client.keys().await;
+
+    let Some(corruptions) = corrupt_events(&keys, logger) else {
+        return;
+    };
+
+    let Some(mut connection) = RawConnection::open(relay.url().as_str(), logger).await else {
+        return;
+    };
+
+    // Catches the events as they come in, whatever the relay makes of their IDs
+    let live_id = SubscriptionId::generate();
+    let ids: Vec<String> = corruptions
+        .iter()
+        .map(|corruption| corruption.id().to_lowercase())
+        .collect();
+    let filter = json!({ "ids": ids });
+
+    if !connection
+        .send_logged(json!(["REQ", live_id, filter]).to_string(), logger)
+        .await
+    {
+        return;
+    }
+
+    let eose = connection
+        .read_until(SUBSCRIPTION_NAME, timeout, logger, |message, logger| {
+            flag_delivered(message, &corruptions, logger);
+            is_end_of_stored_events(message, &live_id)
+        })
+        .await;
+
+    if eose.is_none() {
+        return;
+    }
+
+    logger.log(LogEvent::EstablishedSubscription(SUBSCRIPTION_NAME, &live_id));
+
+    for corruption in &corruptions {
+        let mut case = logger.case();
+        test_rejection(&mut connection, corruption, &corruptions, timeout, &mut case).await;
+        logger.record_case(format!("invalid event: {}", corruption.reason), case);
+    }
+
+    if connection
+        .send_logged(ClientMessage::Close(live_id.clone()).as_json(), logger)
+        .await
+    {
+        logger.log(LogEvent::ClosedSubscription(SUBSCRIPTION_NAME, &live_id));
+    }
+
+    connection.close().await;
+}
+
+/// Sends a single corrupted event, expecting an `OK false` with an `invalid:`
+/// prefix, then queries it by ID to make sure it wasn't stored regardless.
+async fn test_rejection(
+    connection: &mut RawConnection,
+    corruption: &Corruption,
+    corruptions: &[Corruption],
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let reason = corruption.reason;
+
+    if !connection
+        .send_logged(json!(["EVENT", corruption.event]).to_string(), logger)
+        .await
+    {
+        return;
+    }
+
+    let ok = connection
+        .read_until(reason, timeout, logger, |message, logger| {
+            flag_delivered(message, corruptions, logger);
+
+            message[0] == "OK"
+                && message[1]
+                    .as_str()
+                    .is_some_and(|id| id.eq_ignore_ascii_case(corruption.id()))
+        })
+        .await;
+
+    if let Some(ok) = ok {
+        let message = ok[3].as_str().unwrap_or_default();
+
+        let what = format!("event with {reason}");
+
+        if ok[2].as_bool() == Some(false) {
+            match check_prefix("OK", message, logger) {
+                Some(Prefix::Invalid) => logger.log(LogEvent::RejectedInvalid { what: &what, message }),
+                // A policy prefix leaves it unknown whether the relay validates events
+                Some(prefix) => logger.log(LogEvent::MissingExpectedPrefix {
+                    what: &what,
+                    expected: "`invalid:`",
+                    prefix: Some(prefix),
+                    message,
+                }),
+                // Already logged
+                None => {}
+            }
+        } else {
+            logger.log(LogEvent::AcceptedForbidden(&format!("{what}: {ok}")));
+        }
+    }
+
+    let query_id = SubscriptionId::generate();
+    let filter = json!({ "ids": [corruption.id().to_lowercase()] });
+
+    if !connection
+        .send_logged(json!(["REQ", query_id, filter]).to_string(), logger)
+        .await
+    {
+        return;
+    }
+
+    logger.log(LogEvent::EstablishedSubscription(QUERY_NAME, &query_id));
+
+    connection
+        .read_until(QUERY_NAME, timeout, logger, |message, logger| {
+            flag_delivered(message, corruptions, logger);
+            is_end_of_stored_events(message, &query_id)
+        })
+        .await;
+
+    if connection
+        .send_logged(ClientMessage::Close(query_id.clone()).as_json(), logger)
+        .await
+    {
+        logger.log(LogEvent::ClosedSubscription(QUERY_NAME, &query_id));
+    }
+}
+
+/// Signs a text note for every corruption, then tampers with it:
+///
+/// - bad signature: the signature of another event
+/// - bad id: content changed after signing
+/// - wrong pubkey: someone else's pubkey, with the ID hashed accordingly
+/// - uppercase hex: the ID, pubkey and signature in uppercase
+fn corrupt_events(keys: &Keys, logger: &mut Logger) -> Option<Vec<Corruption>> {
+    let note = |label: &str| EventBuilder::new_text_note(format!("nostr-relay-tester: nip01 invalid ({label})"), []);
+    let now = Timestamp::now();
+
+    let mut bad_signature = sign_event(keys, note("bad signature"), now, logger)?;
+    bad_signature.sig = sign_event(keys, note("signature donor"), now, logger)?.sig;
+
+    let mut bad_id = sign_event(keys, note("bad id"), now, logger)?;
+    bad_id.content.push_str(" (tampered)");
+
+    let wrong_pubkey = match note("wrong pubkey")
+        .to_unsigned_event(Keys::generate().public_key())
+        .sign(keys)
+    {
+        Ok(event) => event,
+        Err(error) => {
+            logger.log(LogEvent::FailedToSignEvent(&error));
+            return None;
+        }
+    };
+
+    let mut uppercase_hex = json!(sign_event(keys, note("uppercase hex"), now, logger)?);
+
+    for field in ["id", "pubkey", "sig"] {
+        let uppercase = uppercase_hex[field].as_str().map(str::to_uppercase);
+        uppercase_hex[field] = uppercase.into();
+    }
+
+    Some(vec![
+        Corruption {
+            reason: "bad signature",
+            event: json!(bad_signature),
+        },
+        Corruption {
+            reason: "bad id",
+            event: json!(bad_id),
+        },
+        Corruption {
+            reason: "wrong pubkey",
+            event: json!(wrong_pubkey),
+        },
+        Corruption {
+            reason: "uppercase hex",
+            event: uppercase_hex,
+        },
+    ])
+}
+
+/// Flags the message if it's a corrupted event the relay sent on a
+/// subscription.
+fn flag_delivered(message: &Value, corruptions: &[Corruption], logger: &mut Logger) {
+    if message[0] != "EVENT" {
+        return;
+    }
+
+    let id = message[2]["id"].as_str().unwrap_or_default();
+
+    for corruption in corruptions
+        .iter()
+        .filter(|corruption| corruption.id().eq_ignore_ascii_case(id))
+    {
+        logger.log(LogEvent::DeliveredInvalidEvent {
+            reason: corruption.reason,
+            subscription_id: message[1].as_str().unwrap_or_default(),
+            event: &message[2],
+        });
+    }
+}
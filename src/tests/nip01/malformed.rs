@@ -0,0 +1,74 @@
+use nostr::serde_json::Value;
+
+use crate::tests::{prelude::*, raw::RawConnection};
+
+/// Messages no relay can make sense of, along with the name of the case they
+/// make up.
+const MALFORMED_MESSAGES: [(&str, &str); 10] = [
+    ("truncated JSON", r#"["REQ","nostr-relay-tester",{"kinds":[1]"#),
+    ("not an array", r#"{"REQ":"nostr-relay-tester"}"#),
+    ("EVENT without an event", r#"["EVENT"]"#),
+    ("CLOSE without a subscription id", r#"["CLOSE"]"#),
+    ("unknown verb", r#"["FOO"]"#),
+    ("numeric subscription id", r#"["REQ",42,{"kinds":[1]}]"#),
+    ("string kinds", r#"["REQ","nostr-relay-tester",{"kinds":"1"}]"#),
+    ("numeric authors", r#"["REQ","nostr-relay-tester",{"authors":[1]}]"#),
+    ("string since", r#"["REQ","nostr-relay-tester",{"since":"yesterday"}]"#),
+    ("string limit", r#"["REQ","nostr-relay-tester",{"limit":"10"}]"#),
+];
+
+/// Sends malformed messages, each over a fresh raw connection. The relay must
+/// answer every one of them with a `NOTICE` or a `CLOSED`, and keep the
+/// connection usable, which a follow-up valid `REQ` confirms.
+pub(super) async fn test(relay: &Relay, timeout: Duration, logger: &mut Logger) {
+    let url = relay.url().to_string();
+
+    for (name, message) in MALFORMED_MESSAGES {
+        let mut case = logger.case();
+        test_message(&url, name, message, timeout, &mut case).await;
+        logger.record_case(format!("malformed: {name}"), case);
+    }
+}
+
+async fn test_message(url: &str, name: &str, message: &str, timeout: Duration, logger: &mut Logger) {
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    if !connection.send_logged(message, logger).await {
+        return;
+    }
+
+    // Nothing else was sent on this connection, so the first message has to be the answer, save for
+    // NIP-42 challenges relays may send at any time
+    let Some(reply) = connection
+        .read_until(name, timeout, logger, |message, _| message[0] != "AUTH")
+        .await
+    else {
+        return;
+    };
+
+    check_reply(name, &reply, logger);
+
+    connection.confirm_usable(timeout, logger).await;
+    connection.close().await;
+}
+
+fn check_reply(name: &str, reply: &Value, logger: &mut Logger) {
+    match reply[0].as_str() {
+        // Already logged while reading
+        Some("NOTICE") => {}
+        Some("CLOSED") => {
+            check_prefix("CLOSED", reply[2].as_str().unwrap_or_default(), logger);
+            logger.log(LogEvent::RawReply {
+                name,
+                reply: &reply.to_string(),
+            });
+        }
+        _ => logger.log(LogEvent::UnexpectedReply {
+            name,
+            expected: "NOTICE or CLOSED",
+            reply,
+        }),
+    }
+}
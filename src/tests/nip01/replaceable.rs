@@ -0,0 +1,249 @@
+use nostr::{secp256k1::XOnlyPublicKey, Filter, Metadata};
+
+use crate::tests::prelude::*;
+
+const SUBSCRIPTION_NAME: &str = "new replaceable events";
+const QUERY_NAME: &str = "stored replaceable event";
+
+static REPLACEABLE_TEST_INTERNAL_SUBSCRIPTION_ID: Lazy<InternalSubscriptionId> =
+    Lazy::new(|| InternalSubscriptionId::Custom("replaceable_test".to_owned()));
+
+/// One kind from each replaceable range: metadata, contact lists and
+/// 10000-19999.
+const REPLACEABLE_KINDS: [u64; 3] = [0, 3, 10_444];
+
+/// Publishes sequences of replaceable events for each kind and ensures only
+/// the winning version is ever served.
+pub(super) async fn test(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let keys = Keys::generate();
+
+    for kind in REPLACEABLE_KINDS.map(Kind::from) {
+        let number = kind.as_u64();
+
+        let mut case = logger.case();
+        let current = test_newest_wins(client, relay, connection, &keys, kind, timeout, &mut case).await;
+        logger.record_case(format!("replaceable kind {number}: newest wins"), case);
+
+        let Some(current) = current else {
+            continue;
+        };
+
+        let mut case = logger.case();
+        let current = test_created_at_tie(client, relay, &keys, &current, timeout, &mut case).await;
+        logger.record_case(format!("replaceable kind {number}: lowest id wins ties"), case);
+
+        let Some(current) = current else {
+            continue;
+        };
+
+        let mut case = logger.case();
+        test_no_resurrection(client, relay, &keys, &current, timeout, &mut case).await;
+        logger.record_case(format!("replaceable kind {number}: older version ignored"), case);
+    }
+}
+
+/// Publishes two versions a second apart, expecting both to be delivered live
+/// and only the second to be stored. Returns the second version.
+async fn test_newest_wins(
+    client: &NostrClient,
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    kind: Kind,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Option<Event> {
+    let pubkey = keys.public_key();
+
+    let event_subscription: Option<(SubscriptionId, Timestamp)> = establish_subscription(
+        SUBSCRIPTION_NAME,
+        relay,
+        REPLACEABLE_TEST_INTERNAL_SUBSCRIPTION_ID.clone(),
+        Filter::new().author(pubkey).kind(kind),
+        logger,
+    )
+    .await;
+
+    let mut notifications = client.notifications();
+
+    let base = Timestamp::now();
+    let first = publish_event(relay, connection, keys, version(kind, "first"), base, timeout, logger).await;
+    let second = match first {
+        Some(_) => {
+            let builder = version(kind, "second");
+            publish_event(relay, connection, keys, builder, base + 1_u64, timeout, logger).await
+        }
+        None => None,
+    };
+
+    let (Some(first), Some(second)) = (first, second) else {
+        // The refused version's OK would otherwise reach the listener as unexpected, on top of the refusal
+        if let Some((id, _)) = event_subscription {
+            close_subscription(SUBSCRIPTION_NAME, relay, id, logger).await;
+        }
+
+        return None;
+    };
+
+    if let Some((id, timestamp)) = event_subscription {
+        let check = EventCheck::new()
+            .kind(kind)
+            .author(pubkey)
+            .since(timestamp)
+            .id(first.id)
+            .id(second.id);
+
+        listen(
+            SUBSCRIPTION_NAME,
+            &mut notifications,
+            &id,
+            &check,
+            Until::ReceivedExpected,
+            timeout,
+            logger,
+        )
+        .await;
+
+        close_subscription(SUBSCRIPTION_NAME, relay, id, logger).await;
+    }
+
+    fetch_and_verify_current(client, relay, pubkey, kind, second.id, timeout, logger).await;
+
+    Some(second)
+}
+
+/// Publishes pairs of versions sharing a `created_at` newer than `current`,
+/// once with the higher ID first and once with the lower ID first. Relays may
+/// refuse whichever version loses, but must end up serving the one with the
+/// lowest ID. Returns the last winner.
+async fn test_created_at_tie(
+    client: &NostrClient,
+    relay: &Relay,
+    keys: &Keys,
+    current: &Event,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> Option<Event> {
+    let mut winner = None;
+
+    for (offset, lower_first) in [(1_u64, false), (2_u64, true)] {
+        let created_at = current.created_at + offset;
+
+        let mut pair = [
+            sign_event(
+                keys,
+                version(current.kind, &format!("tie {offset}a")),
+                created_at,
+                logger,
+            )?,
+            sign_event(
+                keys,
+                version(current.kind, &format!("tie {offset}b")),
+                created_at,
+                logger,
+            )?,
+        ];
+        pair.sort_by_key(|event| event.id);
+
+        if !lower_first {
+            pair.reverse();
+        }
+
+        for event in &pair {
+            offer_event(relay, event, timeout, logger).await;
+        }
+
+        let lowest = pair.into_iter().min_by_key(|event| event.id)?;
+
+        fetch_and_verify_current(
+            client,
+            relay,
+            keys.public_key(),
+            current.kind,
+            lowest.id,
+            timeout,
+            logger,
+        )
+        .await;
+
+        winner = Some(lowest);
+    }
+
+    winner
+}
+
+/// Publishes a version older than `current` after it, which must not replace
+/// it.
+async fn test_no_resurrection(
+    client: &NostrClient,
+    relay: &Relay,
+    keys: &Keys,
+    current: &Event,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let Some(outdated) = sign_event(
+        keys,
+        version(current.kind, "outdated"),
+        current.created_at - 10_u64,
+        logger,
+    ) else {
+        return;
+    };
+
+    // Relays may either refuse the outdated version or silently discard it
+    offer_event(relay, &outdated, timeout, logger).await;
+
+    fetch_and_verify_current(
+        client,
+        relay,
+        keys.public_key(),
+        current.kind,
+        current.id,
+        timeout,
+        logger,
+    )
+    .await;
+}
+
+async fn fetch_and_verify_current(
+    client: &NostrClient,
+    relay: &Relay,
+    pubkey: XOnlyPublicKey,
+    kind: Kind,
+    expected_id: EventId,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let stored = fetch_stored_events(
+        QUERY_NAME,
+        client,
+        relay,
+        vec![Filter::new().author(pubkey).kind(kind)],
+        &EventCheck::new().id(expected_id).kind(kind).author(pubkey),
+        timeout,
+        logger,
+    )
+    .await;
+
+    if let Some(events) = stored {
+        verify_stored_events(QUERY_NAME, &events, &[expected_id], logger);
+    }
+}
+
+/// A distinguishable version of a replaceable event, keeping metadata content
+/// valid JSON.
+fn version(kind: Kind, label: &str) -> EventBuilder {
+    let content = format!("nostr-relay-tester: nip01 replaceable ({label})");
+
+    match kind {
+        Kind::Metadata => EventBuilder::set_metadata(&Metadata::new().about(content)),
+        kind => EventBuilder::new(kind, content, []),
+    }
+}
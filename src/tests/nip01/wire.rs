@@ -0,0 +1,172 @@
+use nostr::serde_json::{json, Value};
+use tokio_tungstenite::tungstenite::Message;
+
+use crate::tests::{
+    prelude::*,
+    raw::{Frame, RawConnection},
+};
+
+/// Size of the content of the oversized event, well past the limits relays
+/// commonly enforce.
+const OVERSIZED_CONTENT_LENGTH: usize = 2 * 1024 * 1024;
+
+/// How the relay first reacted to something sent over a raw connection.
+enum Reply {
+    Message(Value),
+    Closed(String),
+    Silence,
+}
+
+/// Sends frames nostr-sdk never would over raw connections, one per case. The
+/// relay is free to answer or not, but must neither crash nor accept anything
+/// it can't make sense of, and must go on serving clients afterwards.
+pub(super) async fn test(client: &NostrClient, relay: &Relay, timeout: Duration, logger: &mut Logger) {
+    let url = relay.url().to_string();
+
+    let mut case = logger.case();
+    test_ping(&url, timeout, &mut case).await;
+    logger.record_case("wire: ping", case);
+
+    let mut case = logger.case();
+    let request = json!(["REQ", SubscriptionId::generate(), { "ids": ["0".repeat(64)] }]);
+    test_frame(
+        &url,
+        "binary frame",
+        Message::Binary(request.to_string().into_bytes()),
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("wire: binary frame", case);
+
+    let mut case = logger.case();
+    let event = oversized_event(&client.keys().await);
+    test_frame(
+        &url,
+        "oversized payload",
+        Message::Text(json!(["EVENT", event]).to_string()),
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("wire: oversized payload", case);
+}
+
+/// Pings the relay, which must answer with a pong carrying the same payload.
+async fn test_ping(url: &str, timeout: Duration, logger: &mut Logger) {
+    const NAME: &str = "pong";
+    const PAYLOAD: &[u8] = b"nostr-relay-tester";
+
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    if let Err(error) = connection.send_frame(Message::Ping(PAYLOAD.to_vec())).await {
+        return logger.log(LogEvent::FailedToSendRawMessage(&error));
+    }
+
+    let pong = async {
+        loop {
+            match connection.recv_frame().await {
+                Some(Ok(Frame::Pong(payload))) if payload == PAYLOAD => return,
+                Some(Ok(_)) => continue,
+                Some(Err(error)) => return logger.log(LogEvent::LostRawConnection(Some(&error), None)),
+                None => return logger.log(LogEvent::LostRawConnection(None, None)),
+            }
+        }
+    };
+
+    if tokio::time::timeout(timeout, pong).await.is_err() {
+        logger.log(LogEvent::TimedOut(NAME, timeout));
+    }
+
+    connection.close().await;
+}
+
+/// Sends a single frame and logs the relay's first reply, then ensures the
+/// relay still serves requests, over a new connection if it closed this one.
+async fn test_frame(url: &str, name: &str, frame: Message, timeout: Duration, logger: &mut Logger) {
+    let Some(mut connection) = RawConnection::open(url, logger).await else {
+        return;
+    };
+
+    // The relay may well drop the connection halfway through an oversized frame
+    let reply = match connection.send_frame(frame).await {
+        Ok(()) => first_reply(&mut connection, timeout, logger).await,
+        Err(error) => Reply::Closed(format!("dropping the connection ({error})")),
+    };
+
+    let connection = match reply {
+        Reply::Message(message) => {
+            logger.log(LogEvent::RawReply {
+                name,
+                reply: &message.to_string(),
+            });
+
+            if message[0] == "OK" && message[2].as_bool() == Some(true) {
+                logger.log(LogEvent::AcceptedForbidden(&format!("event with {name}: {message}")));
+            }
+
+            Some(connection)
+        }
+        Reply::Silence => {
+            logger.log(LogEvent::RawReply { name, reply: "silence" });
+            Some(connection)
+        }
+        Reply::Closed(reply) => {
+            logger.log(LogEvent::RawReply { name, reply: &reply });
+            connection.close().await;
+            RawConnection::open(url, logger).await
+        }
+    };
+
+    if let Some(mut connection) = connection {
+        connection.confirm_usable(timeout, logger).await;
+        connection.close().await;
+    }
+}
+
+/// Waits for the first relay message or the end of the connection, logging
+/// frames that can't be relay messages along the way.
+async fn first_reply(connection: &mut RawConnection, timeout: Duration, logger: &mut Logger) -> Reply {
+    let reply = async {
+        loop {
+            return match connection.recv_frame().await {
+                // NIP-42 challenges can come at any time, they don't answer anything
+                Some(Ok(Frame::Message(message))) if message[0] == "AUTH" => continue,
+                Some(Ok(Frame::Message(message))) => Reply::Message(message),
+                Some(Ok(Frame::Malformed(text))) => {
+                    logger.log(LogEvent::MalformedRelayFrame(&text));
+                    continue;
+                }
+                Some(Ok(Frame::Binary(bytes))) => {
+                    logger.log(LogEvent::BinaryRelayFrame(bytes.len()));
+                    continue;
+                }
+                Some(Ok(Frame::Ping | Frame::Pong(_))) => continue,
+                Some(Ok(Frame::Close(Some(frame)))) => {
+                    Reply::Closed(format!("closing the connection ({}: {:?})", frame.code, frame.reason))
+                }
+                Some(Ok(Frame::Close(None))) | None => Reply::Closed("closing the connection".to_owned()),
+                Some(Err(error)) => Reply::Closed(format!("dropping the connection ({error})")),
+            };
+        }
+    };
+
+    tokio::time::timeout(timeout, reply).await.unwrap_or(Reply::Silence)
+}
+
+/// A text note with megabytes of content, and an ID and signature that can't
+/// be valid, so that it can't end up stored even if the relay accepts its
+/// size.
+fn oversized_event(keys: &Keys) -> Value {
+    json!({
+        "id": "0".repeat(64),
+        "pubkey": keys.public_key().to_string(),
+        "created_at": Timestamp::now().as_u64(),
+        "kind": Kind::TextNote.as_u64(),
+        "tags": [],
+        "content": "x".repeat(OVERSIZED_CONTENT_LENGTH),
+        "sig": "0".repeat(128),
+    })
+}
@@ -1,29 +1,155 @@
+use nostr::{Contact, UncheckedUrl};
+
 use crate::tests::prelude::*;
 
 static CONTACT_LIST_TEST_INTERNAL_SUBSCRIPTION_ID: Lazy<InternalSubscriptionId> =
     Lazy::new(|| InternalSubscriptionId::Custom("contact_list_test".to_owned()));
 
 const SUBSCRIPTION_NAME: &str = "contact list";
+const FETCH_QUERY_NAME: &str = "stored contact list";
 
-pub async fn test(client: &NostrClient, relay: &Relay) -> TestReport {
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
     let span = span!(Level::INFO, "nip02: set contact list").entered();
 
     let mut logger = Logger::new(Nips::Nip02);
+    let mut connection = SharedConnection::new(relay.url().as_str());
+    let keys = client.keys().await;
+    let pubkey = keys.public_key();
 
     let event_subscription: Option<(SubscriptionId, Timestamp)> = establish_subscription(
         SUBSCRIPTION_NAME,
         relay,
         CONTACT_LIST_TEST_INTERNAL_SUBSCRIPTION_ID.clone(),
-        nostr::Filter::new()
-            .author(client.keys().await.public_key())
-            .kind(Kind::ContactList),
+        nostr::Filter::new().author(pubkey).kind(Kind::ContactList),
+        &mut logger,
+    )
+    .await;
+
+    let mut notifications = client.notifications();
+
+    let contacts = vec![
+        Contact::new(
+            Keys::generate().public_key(),
+            Some(UncheckedUrl::from("wss://relay.example.com")),
+            Some("alice"),
+        ),
+        Contact::new(
+            Keys::generate().public_key(),
+            Some(UncheckedUrl::from("wss://relay.example.org")),
+            Some("bob"),
+        ),
+        Contact::new(Keys::generate().public_key(), None, Some("carol")),
+        Contact::new::<String>(Keys::generate().public_key(), None, None),
+    ];
+
+    let published = publish_event(
+        relay,
+        &mut connection,
+        &keys,
+        EventBuilder::set_contact_list(contacts.clone()),
+        Timestamp::now(),
+        timeout,
         &mut logger,
     )
     .await;
 
-    todo!("publish contact list + ensure recved by subscription + fetch and verify");
+    if let Some((id, timestamp)) = event_subscription {
+        if let Some(event) = &published {
+            listen(
+                SUBSCRIPTION_NAME,
+                &mut notifications,
+                &id,
+                &contact_list_check(event).since(timestamp),
+                Until::ReceivedExpected,
+                timeout,
+                &mut logger,
+            )
+            .await;
+        }
+
+        close_subscription(SUBSCRIPTION_NAME, relay, id, &mut logger).await;
+    }
 
     drop(span);
 
-    TestReport::Passed(Nips::Nip02)
+    let span = span!(Level::INFO, "nip02: fetch contact list").entered();
+
+    if let Some(event) = &published {
+        fetch_and_verify_contact_list(client, relay, event, timeout, &mut logger).await;
+    }
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip02: replace contact list").entered();
+
+    if let Some(previous) = &published {
+        // Drop a contact and follow someone new
+        let contacts: Vec<Contact> = contacts[1..]
+            .iter()
+            .cloned()
+            .chain([Contact::new(
+                Keys::generate().public_key(),
+                Some(UncheckedUrl::from("wss://relay.example.net")),
+                Some("dave"),
+            )])
+            .collect();
+
+        let replacement = publish_event(
+            relay,
+            &mut connection,
+            &keys,
+            EventBuilder::set_contact_list(contacts),
+            Timestamp::now().max(previous.created_at + 1_u64),
+            timeout,
+            &mut logger,
+        )
+        .await;
+
+        if let Some(event) = &replacement {
+            fetch_and_verify_contact_list(client, relay, event, timeout, &mut logger).await;
+        }
+    }
+
+    connection.close().await;
+
+    drop(span);
+
+    TestReport::from(logger)
+}
+
+/// The published contact list must be received with every one of its tags,
+/// relay hints and petnames included.
+fn contact_list_check(published: &Event) -> EventCheck {
+    published.tags.iter().fold(
+        EventCheck::new()
+            .id(published.id)
+            .kind(Kind::ContactList)
+            .author(published.pubkey),
+        EventCheck::exact_tag,
+    )
+}
+
+/// Fetches the stored contact list with a fresh REQ and ensures it's the
+/// most recently published one.
+async fn fetch_and_verify_contact_list(
+    client: &NostrClient,
+    relay: &Relay,
+    published: &Event,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let stored = fetch_stored_events(
+        FETCH_QUERY_NAME,
+        client,
+        relay,
+        vec![nostr::Filter::new().author(published.pubkey).kind(Kind::ContactList)],
+        &contact_list_check(published),
+        timeout,
+        logger,
+    )
+    .await;
+
+    if let Some(events) = stored {
+        verify_stored_events(FETCH_QUERY_NAME, &events, &[published.id], logger);
+    }
 }
@@ -1,7 +1,160 @@
-use nostr_sdk::Relay;
+use nostr::secp256k1::XOnlyPublicKey;
 
 use crate::tests::prelude::*;
 
-pub async fn test(client: &NostrClient, relay: &Relay) -> TestReport {
-    todo!()
+const DELETED_QUERY_NAME: &str = "deleted events";
+const DELETION_QUERY_NAME: &str = "deletion event";
+const FOREIGN_DELETION_QUERY_NAME: &str = "event targeted by foreign deletion";
+
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
+    let span = span!(Level::INFO, "nip09: delete events").entered();
+
+    let mut logger = Logger::new(Nips::Nip09);
+    let mut connection = SharedConnection::new(relay.url().as_str());
+    let keys = client.keys().await;
+
+    let mut published = vec![];
+
+    for content in ["nostr-relay-tester: nip09 (1)", "nostr-relay-tester: nip09 (2)"] {
+        let builder = EventBuilder::new_text_note(content, []);
+
+        if let Some(event) = publish_event(
+            relay,
+            &mut connection,
+            &keys,
+            builder,
+            Timestamp::now(),
+            timeout,
+            &mut logger,
+        )
+        .await
+        {
+            published.push(event.id);
+        }
+    }
+
+    let deletion = if published.is_empty() {
+        None
+    } else {
+        let builder = EventBuilder::delete(published.clone());
+
+        publish_event(
+            relay,
+            &mut connection,
+            &keys,
+            builder,
+            Timestamp::now(),
+            timeout,
+            &mut logger,
+        )
+        .await
+    };
+
+    if deletion.is_some() {
+        let stored = fetch_stored_events(
+            DELETED_QUERY_NAME,
+            client,
+            relay,
+            vec![nostr::Filter::new().ids(published.clone())],
+            &EventCheck::new(),
+            timeout,
+            &mut logger,
+        )
+        .await;
+
+        if let Some(events) = stored {
+            verify_stored_events(DELETED_QUERY_NAME, &events, &[], &mut logger);
+        }
+    }
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip09: keep deletion event").entered();
+
+    if let Some(deletion) = &deletion {
+        let check = published.iter().fold(
+            EventCheck::new()
+                .id(deletion.id)
+                .kind(Kind::EventDeletion)
+                .author(keys.public_key()),
+            |check, id| check.tag("e", id.to_hex()),
+        );
+
+        let stored = fetch_stored_events(
+            DELETION_QUERY_NAME,
+            client,
+            relay,
+            vec![nostr::Filter::new().id(deletion.id)],
+            &check,
+            timeout,
+            &mut logger,
+        )
+        .await;
+
+        if let Some(events) = stored {
+            verify_stored_events(DELETION_QUERY_NAME, &events, &[deletion.id], &mut logger);
+        }
+    }
+
+    drop(span);
+
+    let span = span!(Level::INFO, "nip09: ignore foreign deletion").entered();
+
+    let builder = EventBuilder::new_text_note("nostr-relay-tester: nip09 (foreign deletion target)", []);
+
+    if let Some(target) = publish_event(
+        relay,
+        &mut connection,
+        &keys,
+        builder,
+        Timestamp::now(),
+        timeout,
+        &mut logger,
+    )
+    .await
+    {
+        let foreign_keys = Keys::generate();
+
+        if let Some(deletion) = sign_event(
+            &foreign_keys,
+            EventBuilder::delete([target.id]),
+            Timestamp::now(),
+            &mut logger,
+        ) {
+            // Refusing the deletion outright is as good as ignoring it
+            offer_event(relay, &deletion, timeout, &mut logger).await;
+        }
+
+        verify_event_survived(client, relay, target.id, keys.public_key(), timeout, &mut logger).await;
+    }
+
+    connection.close().await;
+
+    drop(span);
+
+    TestReport::from(logger)
+}
+
+async fn verify_event_survived(
+    client: &NostrClient,
+    relay: &Relay,
+    id: EventId,
+    author: XOnlyPublicKey,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let stored = fetch_stored_events(
+        FOREIGN_DELETION_QUERY_NAME,
+        client,
+        relay,
+        vec![nostr::Filter::new().id(id)],
+        &EventCheck::new().id(id).author(author),
+        timeout,
+        logger,
+    )
+    .await;
+
+    if let Some(events) = stored {
+        verify_stored_events(FOREIGN_DELETION_QUERY_NAME, &events, &[id], logger);
+    }
 }
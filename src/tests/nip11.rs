@@ -0,0 +1,482 @@
+use std::{
+    fmt::{Display, Formatter},
+    str::FromStr,
+};
+
+use nostr::{
+    secp256k1::XOnlyPublicKey,
+    serde_json::{self, Map, Value},
+};
+use reqwest::{
+    header::{HeaderMap, ACCEPT},
+    StatusCode,
+};
+
+use crate::tests::prelude::*;
+
+/// Headers NIP-11 requires relays to send so that browsers can read the
+/// document from any origin.
+const CORS_HEADERS: [&str; 3] = [
+    "access-control-allow-origin",
+    "access-control-allow-headers",
+    "access-control-allow-methods",
+];
+
+/// The expected shape of a JSON value in a relay information document.
+enum Shape {
+    String,
+    Integer,
+    Boolean,
+    Array(&'static Shape),
+    Object(&'static [(&'static str, Shape)]),
+    /// Anything goes, for fields too loosely specified to check.
+    Any,
+}
+
+impl Shape {
+    fn matches(&self, value: &Value) -> bool {
+        match self {
+            Shape::String => value.is_string(),
+            Shape::Integer => value.is_u64(),
+            Shape::Boolean => value.is_boolean(),
+            Shape::Array(_) => value.is_array(),
+            Shape::Object(_) => value.is_object(),
+            Shape::Any => true,
+        }
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Shape::String => "a string",
+            Shape::Integer => "a non-negative integer",
+            Shape::Boolean => "a boolean",
+            Shape::Array(_) => "an array",
+            Shape::Object(_) => "an object",
+            Shape::Any => "anything",
+        }
+    }
+}
+
+/// Every field of a relay information document with a known shape. Unknown
+/// fields are left alone, the document is meant to be extended.
+const DOCUMENT: &[(&str, Shape)] = &[
+    ("name", Shape::String),
+    ("description", Shape::String),
+    ("banner", Shape::String),
+    ("icon", Shape::String),
+    ("pubkey", Shape::String),
+    ("self", Shape::String),
+    ("contact", Shape::String),
+    ("supported_nips", Shape::Array(&Shape::Integer)),
+    ("software", Shape::String),
+    ("version", Shape::String),
+    ("privacy_policy", Shape::String),
+    ("terms_of_service", Shape::String),
+    ("limitation", Shape::Object(LIMITATION)),
+    ("retention", Shape::Array(&Shape::Object(RETENTION))),
+    ("relay_countries", Shape::Array(&Shape::String)),
+    ("language_tags", Shape::Array(&Shape::String)),
+    ("tags", Shape::Array(&Shape::String)),
+    ("posting_policy", Shape::String),
+    ("payments_url", Shape::String),
+    ("fees", Shape::Object(FEES)),
+];
+
+const LIMITATION: &[(&str, Shape)] = &[
+    ("max_message_length", Shape::Integer),
+    ("max_subscriptions", Shape::Integer),
+    ("max_filters", Shape::Integer),
+    ("max_limit", Shape::Integer),
+    ("max_subid_length", Shape::Integer),
+    ("max_event_tags", Shape::Integer),
+    ("max_content_length", Shape::Integer),
+    ("min_pow_difficulty", Shape::Integer),
+    ("auth_required", Shape::Boolean),
+    ("payment_required", Shape::Boolean),
+    ("restricted_writes", Shape::Boolean),
+    ("created_at_lower_limit", Shape::Integer),
+    ("created_at_upper_limit", Shape::Integer),
+    ("default_limit", Shape::Integer),
+];
+
+const RETENTION: &[(&str, Shape)] = &[
+    // Either kinds or [start, end] kind ranges
+    ("kinds", Shape::Array(&Shape::Any)),
+    ("time", Shape::Integer),
+    ("count", Shape::Integer),
+];
+
+const FEES: &[(&str, Shape)] = &[
+    ("admission", Shape::Array(&Shape::Object(FEE))),
+    ("subscription", Shape::Array(&Shape::Object(FEE))),
+    ("publication", Shape::Array(&Shape::Object(FEE))),
+];
+
+const FEE: &[(&str, Shape)] = &[
+    ("amount", Shape::Integer),
+    ("unit", Shape::String),
+    ("period", Shape::Integer),
+    ("kinds", Shape::Array(&Shape::Integer)),
+];
+
+/// A relay information document along with the headers it was served with.
+pub struct RelayInformation {
+    pub document: Map<String, Value>,
+    headers: HeaderMap,
+}
+
+#[cfg(test)]
+impl RelayInformation {
+    /// A document as if it had been served without any headers.
+    pub fn from_document(document: Value) -> RelayInformation {
+        RelayInformation {
+            document: serde_json::from_value(document).unwrap(),
+            headers: HeaderMap::new(),
+        }
+    }
+}
+
+/// The relay information document fetched at the start of the run, or why it
+/// couldn't be.
+pub type Information<'a> = Result<&'a RelayInformation, &'a InformationError>;
+
+#[derive(Debug)]
+pub enum InformationError {
+    Request(reqwest::Error),
+    Status(StatusCode),
+    /// The document isn't JSON, or isn't an object.
+    Malformed(serde_json::Error),
+    /// No selected test needed the document, so it wasn't fetched.
+    NotFetched,
+}
+
+impl Display for InformationError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InformationError::Request(error) => write!(f, "{error}"),
+            InformationError::Status(status) => write!(f, "relay served it with status {status}"),
+            InformationError::Malformed(error) => write!(f, "it isn't a JSON object: {error}"),
+            InformationError::NotFetched => write!(f, "it wasn't fetched, as no selected test needed it"),
+        }
+    }
+}
+
+impl RelayInformation {
+    /// Fetches the relay information document from the relay's URL, over
+    /// http(s) instead of ws(s).
+    pub async fn fetch(relay_url: &str, timeout: Duration) -> Result<RelayInformation, InformationError> {
+        let url = match relay_url.split_once("://") {
+            Some(("wss", rest)) => format!("https://{rest}"),
+            Some(("ws", rest)) => format!("http://{rest}"),
+            _ => relay_url.to_owned(),
+        };
+
+        let response = reqwest::Client::new()
+            .get(url)
+            .header(ACCEPT, "application/nostr+json")
+            .timeout(timeout)
+            .send()
+            .await
+            .map_err(InformationError::Request)?;
+
+        if !response.status().is_success() {
+            return Err(InformationError::Status(response.status()));
+        }
+
+        let headers = response.headers().clone();
+        let body = response.text().await.map_err(InformationError::Request)?;
+        let document = serde_json::from_str(&body).map_err(InformationError::Malformed)?;
+
+        Ok(RelayInformation { document, headers })
+    }
+
+    /// The NIPs the relay claims to support, leaving out anything that isn't
+    /// a NIP number.
+    pub fn supported_nips(&self) -> Vec<u16> {
+        self.document
+            .get("supported_nips")
+            .and_then(Value::as_array)
+            .into_iter()
+            .flatten()
+            .filter_map(|nip| nip.as_u64()?.try_into().ok())
+            .collect()
+    }
+
+    /// One of the limitations the relay advertises.
+    pub fn limitation(&self, name: &str) -> Option<&Value> {
+        self.document.get("limitation")?.get(name)
+    }
+}
+
+/// Checks that the relay information document fetched at the start of the
+/// run could be, that it's served with CORS headers, that every known field
+/// has the right shape, and that the advertised pubkey is a valid key.
+pub fn test(information: Information) -> TestReport {
+    let span = span!(Level::INFO, "nip11: relay information document").entered();
+
+    let mut logger = Logger::new(Nips::Nip11);
+    check_information(information, &mut logger);
+
+    drop(span);
+
+    logger.into()
+}
+
+fn check_information(information: Information, logger: &mut Logger) {
+    let mut case = logger.case();
+
+    match information {
+        Ok(information) => case.log(LogEvent::FetchedRelayInformation(&information.document)),
+        Err(error) => case.log(LogEvent::FailedToFetchRelayInformation(error)),
+    }
+
+    logger.record_case("relay information: fetch", case);
+
+    let Ok(information) = information else {
+        return;
+    };
+
+    let mut case = logger.case();
+
+    for header in CORS_HEADERS {
+        if !information.headers.contains_key(header) {
+            case.log(LogEvent::MissingCorsHeader(header));
+        }
+    }
+
+    logger.record_case("relay information: CORS headers", case);
+
+    let mut case = logger.case();
+    check_fields("", &information.document, DOCUMENT, &mut case);
+    logger.record_case("relay information: schema", case);
+
+    let mut case = logger.case();
+
+    match information.document.get("pubkey").and_then(Value::as_str) {
+        Some(pubkey) => check_pubkey(pubkey, &mut case),
+        None => case.log(LogEvent::MissingInformationPubkey),
+    }
+
+    logger.record_case("relay information: pubkey", case);
+}
+
+/// Checks every known field of the object against its expected shape,
+/// recursing into arrays and objects. `path` is where the object sits in the
+/// document, for logging.
+fn check_fields(path: &str, object: &Map<String, Value>, fields: &[(&str, Shape)], logger: &mut Logger) {
+    for (name, shape) in fields {
+        if let Some(value) = object.get(*name) {
+            check_value(&format!("{path}{name}"), value, shape, logger);
+        }
+    }
+}
+
+fn check_value(path: &str, value: &Value, shape: &Shape, logger: &mut Logger) {
+    if !shape.matches(value) {
+        return logger.log(LogEvent::InvalidInformationField {
+            field: path,
+            expected: shape.describe(),
+            value,
+        });
+    }
+
+    match (shape, value) {
+        (Shape::Array(item), Value::Array(items)) => {
+            for (index, value) in items.iter().enumerate() {
+                check_value(&format!("{path}[{index}]"), value, item, logger);
+            }
+        }
+        (Shape::Object(fields), Value::Object(object)) => check_fields(&format!("{path}."), object, fields, logger),
+        _ => {}
+    }
+}
+
+/// The pubkey must be in the same lowercase hex form as in events, and be an
+/// actual key.
+fn check_pubkey(pubkey: &str, logger: &mut Logger) {
+    let is_lowercase_hex = pubkey.len() == 64 && pubkey.chars().all(|c| c.is_ascii_digit() || ('a'..='f').contains(&c));
+
+    if !is_lowercase_hex {
+        return logger.log(LogEvent::InvalidInformationPubkey {
+            pubkey,
+            reason: "it isn't 64 lowercase hex characters",
+        });
+    }
+
+    if let Err(error) = XOnlyPublicKey::from_str(pubkey) {
+        logger.log(LogEvent::InvalidInformationPubkey {
+            pubkey,
+            reason: &format!("it isn't a valid key: {error}"),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nostr::serde_json::json;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+    use crate::tests::report::Status;
+
+    const PUBKEY: &str = "3bf0c63fcb93463407af97a5e5ee64fa883d107ef9e558472c4eb9aaaefa459d";
+
+    /// Serves HTTP on a local port, answering every request with `respond`,
+    /// which gets the request with its headers lowercased. Returns the relay
+    /// URL to fetch the document from.
+    async fn serve(respond: fn(&str) -> String) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+
+        tokio::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => break,
+                        Ok(read) => request.extend_from_slice(&buffer[..read]),
+                    }
+                }
+
+                let response = respond(&String::from_utf8_lossy(&request).to_lowercase());
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+
+        format!("ws://{address}")
+    }
+
+    fn response(content_type: &str, cors: bool, body: &str) -> String {
+        let cors = if cors {
+            "access-control-allow-origin: *\r\naccess-control-allow-headers: *\r\naccess-control-allow-methods: GET\r\n"
+        } else {
+            ""
+        };
+
+        format!(
+            "HTTP/1.1 200 OK\r\ncontent-type: {content_type}\r\n{cors}content-length: {}\r\nconnection: close\r\n\r\n{body}",
+            body.len()
+        )
+    }
+
+    /// Only serves the document when asked for it, the way relays sharing
+    /// their URL with a web page do.
+    fn document(request: &str, body: Value) -> String {
+        if request.contains("accept: application/nostr+json") {
+            response("application/nostr+json", true, &body.to_string())
+        } else {
+            response("text/html", true, "<html>a relay</html>")
+        }
+    }
+
+    /// Fetches the document from the server and runs every check on it,
+    /// returning the events they logged and the resulting status.
+    async fn check(respond: fn(&str) -> String) -> (Vec<&'static str>, Status) {
+        let url = serve(respond).await;
+        let information = RelayInformation::fetch(&url, Duration::from_secs(5)).await;
+
+        let mut logger = Logger::new(Nips::Nip11);
+        check_information(information.as_ref(), &mut logger);
+
+        let logged = logger.logged().to_vec();
+        (logged, TestReport::from(logger).status())
+    }
+
+    #[tokio::test]
+    async fn good_document() {
+        let (logged, status) = check(|request| {
+            document(
+                request,
+                json!({
+                    "name": "test relay",
+                    "pubkey": PUBKEY,
+                    "supported_nips": [1, 11],
+                    "limitation": { "max_limit": 500, "auth_required": false },
+                }),
+            )
+        })
+        .await;
+
+        assert_eq!(logged, ["FetchedRelayInformation"]);
+        assert_eq!(status, Status::Passed);
+    }
+
+    #[tokio::test]
+    async fn html_instead_of_document() {
+        let (logged, status) = check(|_| response("text/html", true, "<html>a relay</html>")).await;
+
+        assert_eq!(logged, ["FailedToFetchRelayInformation"]);
+        assert_eq!(status, Status::Failed);
+    }
+
+    #[tokio::test]
+    async fn missing_cors_headers() {
+        let (logged, status) = check(|_| {
+            let body = json!({ "pubkey": PUBKEY }).to_string();
+            response("application/nostr+json", false, &body)
+        })
+        .await;
+
+        assert_eq!(
+            logged,
+            [
+                "FetchedRelayInformation",
+                "MissingCorsHeader",
+                "MissingCorsHeader",
+                "MissingCorsHeader"
+            ]
+        );
+        assert_eq!(status, Status::Failed);
+    }
+
+    #[tokio::test]
+    async fn wrong_field_types() {
+        let (logged, status) = check(|request| {
+            document(
+                request,
+                json!({
+                    "name": 5,
+                    "pubkey": PUBKEY,
+                    "supported_nips": [1, "11"],
+                    "limitation": { "max_limit": -1, "auth_required": "no" },
+                    "relay_countries": "CA",
+                }),
+            )
+        })
+        .await;
+
+        assert_eq!(
+            logged,
+            [
+                "FetchedRelayInformation",
+                "InvalidInformationField",
+                "InvalidInformationField",
+                "InvalidInformationField",
+                "InvalidInformationField",
+                "InvalidInformationField",
+            ]
+        );
+        assert_eq!(status, Status::Failed);
+    }
+
+    #[tokio::test]
+    async fn malformed_pubkey() {
+        let (logged, status) = check(|request| document(request, json!({ "pubkey": "npub1relay" }))).await;
+
+        assert_eq!(logged, ["FetchedRelayInformation", "InvalidInformationPubkey"]);
+        assert_eq!(status, Status::Failed);
+
+        // Uppercase, too short, and beyond the curve's field
+        for pubkey in [PUBKEY.to_uppercase(), PUBKEY[..63].to_owned(), "f".repeat(64)] {
+            let mut logger = Logger::new(Nips::Nip11);
+            check_pubkey(&pubkey, &mut logger);
+
+            assert_eq!(logger.logged(), ["InvalidInformationPubkey"], "{pubkey}");
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use std::ops::RangeInclusive;
+
+use nostr::{nips::nip13, secp256k1::XOnlyPublicKey, Tag};
+
+use crate::tests::{
+    nip11::{Information, RelayInformation},
+    prelude::*,
+};
+
+/// The highest difficulty events are mined to.
+const MAX_MINED_DIFFICULTY: u8 = 24;
+
+/// Publishes events mined below, at and above the target difficulty, which
+/// is either configured or the relay's advertised `min_pow_difficulty`.
+/// Events below it must be rejected with a `pow:` prefix and the others
+/// accepted, unless their `nonce` tag commits to a lower target than
+/// required, since their work then only reached it by chance.
+///
+/// Mining takes about 2^difficulty hashes per event, so events above
+/// [`MAX_MINED_DIFFICULTY`] are skipped rather than mined for minutes on end.
+pub async fn test(
+    client: &NostrClient,
+    relay: &Relay,
+    timeout: Duration,
+    target: Option<u8>,
+    information: Information<'_>,
+) -> TestReport {
+    let span = span!(Level::INFO, "nip13: proof of work").entered();
+
+    let mut logger = Logger::new(Nips::Nip13);
+    let keys = client.keys().await;
+
+    let target = match (target, information) {
+        (Some(target), _) => Some(target),
+        (None, Ok(information)) => advertised_difficulty(information),
+        (None, Err(error)) => {
+            logger.log(LogEvent::FailedToFetchRelayInformation(error));
+            drop(span);
+            return logger.into();
+        }
+    };
+
+    let Some(target) = target else {
+        logger.log(LogEvent::Skipped(
+            "relay doesn't advertise a min_pow_difficulty and none was configured, there's no target to test",
+        ));
+        drop(span);
+        return logger.into();
+    };
+
+    // Nothing is below a target of 0
+    if let Some(below) = target.checked_sub(1) {
+        let mut case = logger.case();
+
+        if let Some((event, difficulty)) = mine(&keys, "below target", below, below..=below, &mut case).await {
+            expect_refused(relay, &event, difficulty, below, target, timeout, &mut case).await;
+        }
+
+        logger.record_case("pow: below target", case);
+    }
+
+    let mut case = logger.case();
+
+    if let Some((event, difficulty)) = mine(&keys, "at target", target, target..=u8::MAX, &mut case).await {
+        expect_accepted(relay, &event, difficulty, target, target, timeout, &mut case).await;
+    }
+
+    logger.record_case("pow: at target", case);
+
+    if let Some(above) = target.checked_add(1) {
+        let mut case = logger.case();
+
+        if let Some((event, difficulty)) = mine(&keys, "above target", above, above..=u8::MAX, &mut case).await {
+            expect_accepted(relay, &event, difficulty, above, target, timeout, &mut case).await;
+        }
+
+        logger.record_case("pow: above target", case);
+    }
+
+    if let Some(below) = target.checked_sub(1) {
+        let mut case = logger.case();
+
+        if let Some((event, difficulty)) =
+            mine(&keys, "committed below target", below, target..=u8::MAX, &mut case).await
+        {
+            expect_refused(relay, &event, difficulty, below, target, timeout, &mut case).await;
+        }
+
+        logger.record_case("pow: committed below target", case);
+    }
+
+    drop(span);
+
+    logger.into()
+}
+
+/// The relay's advertised `min_pow_difficulty`, if it has one.
+fn advertised_difficulty(information: &RelayInformation) -> Option<u8> {
+    information.limitation("min_pow_difficulty")?.as_u64()?.try_into().ok()
+}
+
+/// Mines a text note whose `nonce` tag commits to `committed`, until its ID
+/// has a difficulty within `difficulty`. Returns the signed event along with
+/// that difficulty.
+async fn mine(
+    keys: &Keys,
+    description: &str,
+    committed: u8,
+    difficulty: RangeInclusive<u8>,
+    logger: &mut Logger,
+) -> Option<(Event, u8)> {
+    if *difficulty.start() > MAX_MINED_DIFFICULTY {
+        logger.log(LogEvent::Skipped(&format!(
+            "mining an event {description}, of difficulty {}, takes over 2^{MAX_MINED_DIFFICULTY} hashes",
+            difficulty.start()
+        )));
+        return None;
+    }
+
+    let pubkey = keys.public_key();
+    let content = format!("nostr-relay-tester: nip13 ({description})");
+    let created_at = Timestamp::now();
+
+    // Hashing would hold up every other task on the runtime's worker for as long as it takes
+    let search = {
+        let content = content.clone();
+        tokio::task::spawn_blocking(move || find_nonce(&pubkey, created_at, &content, committed, difficulty))
+    };
+
+    let (tags, mined) = search
+        .await
+        .unwrap_or_else(|error| std::panic::resume_unwind(error.into_panic()))?;
+
+    let event = sign_event(keys, EventBuilder::new_text_note(content, tags), created_at, logger)?;
+
+    logger.log(LogEvent::MinedEvent {
+        id: &event.id,
+        difficulty: mined,
+        committed,
+    });
+
+    Some((event, mined))
+}
+
+/// Tries nonces until the ID of the text note they make has a difficulty
+/// within `difficulty`. Returns the `nonce` tag along with that difficulty.
+fn find_nonce(
+    pubkey: &XOnlyPublicKey,
+    created_at: Timestamp,
+    content: &str,
+    committed: u8,
+    difficulty: RangeInclusive<u8>,
+) -> Option<(Vec<Tag>, u8)> {
+    (0_u128..)
+        .map(|nonce| {
+            let tags = vec![Tag::POW {
+                nonce,
+                difficulty: committed,
+            }];
+            let id = EventId::new(pubkey, created_at, &Kind::TextNote, &tags, content);
+
+            (tags, nip13::get_leading_zero_bits(id.inner()))
+        })
+        .find(|(_, mined)| difficulty.contains(mined))
+}
+
+async fn expect_accepted(
+    relay: &Relay,
+    event: &Event,
+    difficulty: u8,
+    committed: u8,
+    target: u8,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    match relay.send_event(event.clone(), send_options(timeout)).await {
+        Ok(id) => logger.log(LogEvent::PublishedEvent(&id)),
+        Err(nostr_sdk::relay::Error::EventNotPublished(message)) => {
+            let prefix = check_prefix("OK", &message, logger);
+
+            if prefix == Some(Prefix::Pow) {
+                logger.log(LogEvent::RejectedSufficientWork {
+                    id: &event.id,
+                    difficulty,
+                    committed,
+                    target,
+                    message: &message,
+                });
+            } else {
+                logger.log(LogEvent::RefusedEvent {
+                    id: &event.id,
+                    prefix,
+                    message: &message,
+                });
+            }
+        }
+        Err(error) => logger.log(LogEvent::FailedToPublishEvent(&error)),
+    }
+}
+
+async fn expect_refused(
+    relay: &Relay,
+    event: &Event,
+    difficulty: u8,
+    committed: u8,
+    target: u8,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    match relay.send_event(event.clone(), send_options(timeout)).await {
+        Ok(_) => logger.log(LogEvent::AcceptedForbidden(&format!(
+            "event {} with a difficulty of {difficulty} committing to {committed}, below the target of {target}",
+            event.id
+        ))),
+        Err(nostr_sdk::relay::Error::EventNotPublished(message)) => match check_prefix("OK", &message, logger) {
+            Some(Prefix::Pow) => logger.log(LogEvent::RejectedEvent(&event.id, &message)),
+            prefix => logger.log(LogEvent::MissingExpectedPrefix {
+                what: "an event lacking proof of work",
+                expected: "`pow:`",
+                prefix,
+                message: &message,
+            }),
+        },
+        Err(error) => logger.log(LogEvent::FailedToPublishEvent(&error)),
+    }
+}
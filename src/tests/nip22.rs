@@ -0,0 +1,125 @@
+use crate::tests::{nip11::Information, prelude::*};
+
+/// How far from now the events far in the past and in the future are dated.
+const FAR_SECS: u64 = 365 * 24 * 60 * 60;
+
+/// Publishes events dated near now, far in the past and far in the future.
+/// The one near now must be accepted. Whether the others are is compared
+/// against the relay's advertised `created_at_lower_limit` and
+/// `created_at_upper_limit`, if any: events beyond a limit must be rejected
+/// and those within it accepted.
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration, information: Information<'_>) -> TestReport {
+    let span = span!(Level::INFO, "nip22: created_at bounds").entered();
+
+    let mut logger = Logger::new(Nips::Nip22);
+    let mut connection = SharedConnection::new(relay.url().as_str());
+    let keys = client.keys().await;
+
+    if let Err(error) = information {
+        logger.log(LogEvent::Skipped(&format!(
+            "the relay information document couldn't be fetched ({error}), the events far away can't be checked \
+             against any limit"
+        )));
+    }
+
+    let limit = |name| {
+        let limit = information.ok()?.limitation(name)?.as_u64()?;
+        Some((name, limit))
+    };
+
+    let mut case = logger.case();
+    let builder = EventBuilder::new_text_note("nostr-relay-tester: nip22 (near now)", []);
+    publish_event(
+        relay,
+        &mut connection,
+        &keys,
+        builder,
+        Timestamp::now(),
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("created_at: near now", case);
+
+    let mut case = logger.case();
+    test_bound(
+        relay,
+        &keys,
+        "far in the past",
+        Timestamp::now() - FAR_SECS,
+        limit("created_at_lower_limit"),
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("created_at: far in the past", case);
+
+    let mut case = logger.case();
+    test_bound(
+        relay,
+        &keys,
+        "far in the future",
+        Timestamp::now() + FAR_SECS,
+        limit("created_at_upper_limit"),
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("created_at: far in the future", case);
+
+    connection.close().await;
+
+    drop(span);
+
+    logger.into()
+}
+
+/// Offers an event dated [`FAR_SECS`] away from now. Either outcome is
+/// fine without a limit, otherwise the event must be rejected if it's beyond
+/// the limit and accepted if it's within it.
+async fn test_bound(
+    relay: &Relay,
+    keys: &Keys,
+    description: &str,
+    created_at: Timestamp,
+    limit: Option<(&str, u64)>,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let builder = EventBuilder::new_text_note(format!("nostr-relay-tester: nip22 ({description})"), []);
+
+    let Some(event) = sign_event(keys, builder, created_at, logger) else {
+        return;
+    };
+
+    let accepted = match relay.send_event(event.clone(), send_options(timeout)).await {
+        Ok(id) => {
+            logger.log(LogEvent::PublishedEvent(&id));
+            true
+        }
+        Err(nostr_sdk::relay::Error::EventNotPublished(message)) => {
+            check_prefix("OK", &message, logger);
+            logger.log(LogEvent::RejectedEvent(&event.id, &message));
+            false
+        }
+        Err(error) => return logger.log(LogEvent::FailedToPublishEvent(&error)),
+    };
+
+    let Some((name, limit)) = limit else {
+        return;
+    };
+
+    let claim = format!("{name}: {limit}");
+
+    if FAR_SECS > limit && accepted {
+        logger.log(LogEvent::BrokenClaim {
+            claim: &claim,
+            reality: &format!("it accepted an event dated {FAR_SECS} seconds away, {description}"),
+        });
+    } else if FAR_SECS <= limit && !accepted {
+        logger.log(LogEvent::BrokenClaim {
+            claim: &claim,
+            reality: &format!("it rejected an event dated {FAR_SECS} seconds away, {description}"),
+        });
+    }
+}
@@ -0,0 +1,167 @@
+use nostr::Tag;
+
+use crate::tests::prelude::*;
+
+const EXPIRED_QUERY_NAME: &str = "already expired event";
+const BEFORE_EXPIRY_QUERY_NAME: &str = "short-lived event before expiry";
+const AFTER_EXPIRY_QUERY_NAME: &str = "short-lived event after expiry";
+
+/// How long before being published the already expired event expired.
+const EXPIRED_AGE_SECS: u64 = 60;
+
+/// How many timeouts the short-lived event lives for. Publishing and querying
+/// it take up to a timeout each, on top of checking it was stored.
+const LIFETIME_TIMEOUTS: u32 = 4;
+
+/// The shortest lifetime of the short-lived event, so that a tiny timeout
+/// doesn't get it to expire before it's even queried.
+const MIN_LIFETIME_SECS: u64 = 4;
+
+/// Publishes an event that already expired, which must be rejected or never
+/// served, and a short-lived one, which must be served until it expires and
+/// not after. The short-lived event lives for a few timeouts, so a lower
+/// timeout makes for a quicker test. Queries go over a raw connection, as
+/// nostr-sdk drops expired events by itself.
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
+    let span = span!(Level::INFO, "nip40: expiration").entered();
+
+    let mut logger = Logger::new(Nips::Nip40);
+    let keys = client.keys().await;
+    let mut connection = SharedConnection::new(relay.url().as_str());
+
+    let mut case = logger.case();
+    test_already_expired(relay, &mut connection, &keys, timeout, &mut case).await;
+    logger.record_case("expiration: already expired", case);
+
+    test_short_lived(relay, &mut connection, &keys, timeout, &mut logger).await;
+
+    connection.close().await;
+
+    drop(span);
+
+    logger.into()
+}
+
+async fn test_already_expired(
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let expiration = Timestamp::now() - EXPIRED_AGE_SECS;
+
+    let Some(event) = sign_event(
+        keys,
+        expiring("nostr-relay-tester: nip40 (already expired)", expiration),
+        expiration - EXPIRED_AGE_SECS,
+        logger,
+    ) else {
+        return;
+    };
+
+    // Rejecting it outright is as good as never serving it
+    if offer_event(relay, &event, timeout, logger).await {
+        check_served(
+            connection,
+            EXPIRED_QUERY_NAME,
+            &event,
+            expiration,
+            false,
+            timeout,
+            logger,
+        )
+        .await;
+    }
+}
+
+/// Publishes an event expiring after a few timeouts, queries it right away,
+/// then again once it expired.
+async fn test_short_lived(
+    relay: &Relay,
+    connection: &mut SharedConnection,
+    keys: &Keys,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let mut before = logger.case();
+
+    let lifetime = (timeout * LIFETIME_TIMEOUTS).as_secs().max(MIN_LIFETIME_SECS);
+    let expiration = Timestamp::now() + lifetime;
+    let builder = expiring("nostr-relay-tester: nip40 (short-lived)", expiration);
+
+    let Some(event) = publish_event(relay, connection, keys, builder, Timestamp::now(), timeout, &mut before).await
+    else {
+        return logger.record_case("expiration: served before expiry", before);
+    };
+
+    check_served(
+        connection,
+        BEFORE_EXPIRY_QUERY_NAME,
+        &event,
+        expiration,
+        true,
+        timeout,
+        &mut before,
+    )
+    .await;
+    logger.record_case("expiration: served before expiry", before);
+
+    // Timestamps only have a precision of a second, so the extra one makes sure the expiration passed
+    let remaining = expiration.as_u64().saturating_sub(Timestamp::now().as_u64()) + 1;
+    tokio::time::sleep(Duration::from_secs(remaining)).await;
+
+    let mut after = logger.case();
+    check_served(
+        connection,
+        AFTER_EXPIRY_QUERY_NAME,
+        &event,
+        expiration,
+        false,
+        timeout,
+        &mut after,
+    )
+    .await;
+    logger.record_case("expiration: not served after expiry", after);
+}
+
+/// Queries the event by ID over the raw connection, checking whether the
+/// relay serves it before EOSE as `expected`.
+async fn check_served(
+    connection: &mut SharedConnection,
+    name: &str,
+    event: &Event,
+    expiration: Timestamp,
+    expected: bool,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let Some(events) = connection.query_by_id(name, &event.id, timeout, logger).await else {
+        return;
+    };
+
+    let event_id = event.id.to_hex();
+    let served = events.iter().any(|served| served["id"].as_str() == Some(&event_id));
+
+    if served && !expected {
+        logger.log(LogEvent::ServedExpiredEvent {
+            name,
+            id: &event.id,
+            expiration,
+        });
+    } else if !served && expected {
+        // Round trips slower than the timeouts they're allowed could still outlast the event
+        if Timestamp::now() < expiration {
+            logger.log(LogEvent::MissingStoredEvent { name, id: &event.id });
+        } else {
+            logger.log(LogEvent::Skipped(&format!(
+                "the event expired before the relay answered the {name} query"
+            )));
+        }
+    }
+}
+
+/// A text note carrying a NIP-40 `expiration` tag.
+fn expiring(content: &str, expiration: Timestamp) -> EventBuilder {
+    EventBuilder::new_text_note(content, [Tag::Expiration(expiration)])
+}
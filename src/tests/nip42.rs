@@ -0,0 +1,362 @@
+use nostr::{
+    serde_json::{json, Value},
+    Tag, UncheckedUrl,
+};
+
+use crate::tests::{prelude::*, raw::RawConnection};
+
+const SUBSCRIPTION_NAME: &str = "unauthenticated events";
+const CHALLENGE_NAME: &str = "AUTH challenge";
+
+/// How far in the past the stale authentication event is dated, well beyond
+/// the ~10 minutes NIP-42 suggests relays allow.
+const STALE_AGE_SECS: u64 = 60 * 60;
+
+/// The ways an authentication event is corrupted to check that the relay
+/// rejects it.
+#[derive(Copy, Clone)]
+enum InvalidResponse {
+    WrongChallenge,
+    WrongRelay,
+    Stale,
+}
+
+impl InvalidResponse {
+    const ALL: [InvalidResponse; 3] = [
+        InvalidResponse::WrongChallenge,
+        InvalidResponse::WrongRelay,
+        InvalidResponse::Stale,
+    ];
+
+    fn reason(&self) -> &'static str {
+        match self {
+            InvalidResponse::WrongChallenge => "wrong challenge",
+            InvalidResponse::WrongRelay => "wrong relay tag",
+            InvalidResponse::Stale => "stale created_at",
+        }
+    }
+
+    /// Signs an authentication event that's valid but for this one flaw.
+    fn sign(&self, keys: &Keys, challenge: &str, relay_url: &str, logger: &mut Logger) -> Option<Event> {
+        let (builder, created_at) = match self {
+            InvalidResponse::WrongChallenge => (response(&format!("{challenge}-wrong"), relay_url), Timestamp::now()),
+            InvalidResponse::WrongRelay => (response(challenge, "wss://relay.invalid/"), Timestamp::now()),
+            InvalidResponse::Stale => (response(challenge, relay_url), Timestamp::now() - STALE_AGE_SECS),
+        };
+
+        sign_event(keys, builder, created_at, logger)
+    }
+}
+
+/// A raw connection that keeps track of the last challenge the relay sent,
+/// whenever it did.
+pub(super) struct Session {
+    pub(super) connection: RawConnection,
+    challenge: Option<String>,
+}
+
+impl Session {
+    pub(super) async fn open(url: &str, logger: &mut Logger) -> Option<Session> {
+        Some(Session {
+            connection: RawConnection::open(url, logger).await?,
+            challenge: None,
+        })
+    }
+
+    async fn read_until<F: FnMut(&Value) -> bool>(
+        &mut self,
+        name: &str,
+        timeout: Duration,
+        logger: &mut Logger,
+        mut stop: F,
+    ) -> Option<Value> {
+        let challenge = &mut self.challenge;
+
+        self.connection
+            .read_until(name, timeout, logger, |message, _| {
+                if message[0] == "AUTH" {
+                    *challenge = message[1].as_str().map(str::to_owned);
+                }
+
+                stop(message)
+            })
+            .await
+    }
+
+    /// Waits for a challenge, unless the relay already sent one.
+    pub(super) async fn challenge(&mut self, timeout: Duration, logger: &mut Logger) -> Option<String> {
+        if self.challenge.is_none() {
+            self.read_until(CHALLENGE_NAME, timeout, logger, |message| message[0] == "AUTH")
+                .await;
+        }
+
+        self.challenge.clone()
+    }
+
+    /// Subscribes to the tester's own events, returning the `EOSE` or the
+    /// `CLOSED` that answers the subscription.
+    async fn subscribe(&mut self, keys: &Keys, timeout: Duration, logger: &mut Logger) -> Option<Value> {
+        let id = SubscriptionId::generate();
+        let filter = json!({ "authors": [keys.public_key()], "limit": 1 });
+
+        if !self
+            .connection
+            .send_logged(json!(["REQ", id, filter]).to_string(), logger)
+            .await
+        {
+            return None;
+        }
+
+        let answer = self
+            .read_until(SUBSCRIPTION_NAME, timeout, logger, |message| {
+                (message[0] == "EOSE" || message[0] == "CLOSED") && message[1].as_str() == Some(&id.to_string())
+            })
+            .await?;
+
+        if answer[0] == "EOSE" {
+            self.connection
+                .send_logged(json!(["CLOSE", id]).to_string(), logger)
+                .await;
+        }
+
+        Some(answer)
+    }
+
+    /// Sends the event with the given verb, returning the `OK` that answers
+    /// it.
+    pub(super) async fn send_event(
+        &mut self,
+        verb: &str,
+        event: &Event,
+        timeout: Duration,
+        logger: &mut Logger,
+    ) -> Option<Value> {
+        if !self
+            .connection
+            .send_logged(json!([verb, event]).to_string(), logger)
+            .await
+        {
+            return None;
+        }
+
+        let id = event.id.to_hex();
+
+        self.read_until(&format!("OK for {verb}"), timeout, logger, |message| {
+            message[0] == "OK" && message[1].as_str() == Some(&id)
+        })
+        .await
+    }
+}
+
+/// Authenticates with the relay's challenge, then with authentication events
+/// that must be rejected: one for another challenge, one for another relay,
+/// and a stale one. If the relay refuses to serve or store events before
+/// authentication, it must do so with `auth-required:` and serve them after.
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
+    let span = span!(Level::INFO, "nip42: authentication").entered();
+
+    let mut logger = Logger::new(Nips::Nip42);
+    let keys = client.keys().await;
+    let url = relay.url().to_string();
+
+    test_authentication(&url, &keys, timeout, &mut logger).await;
+
+    for invalid in InvalidResponse::ALL {
+        let mut case = logger.case();
+        test_invalid_response(&url, &keys, invalid, timeout, &mut case).await;
+        logger.record_case(format!("auth: {}", invalid.reason()), case);
+    }
+
+    drop(span);
+
+    logger.into()
+}
+
+/// Waits for a challenge, trying to read and store events in the meantime,
+/// then answers it. Whatever the relay refused before must work afterwards.
+async fn test_authentication(url: &str, keys: &Keys, timeout: Duration, logger: &mut Logger) {
+    let mut case = logger.case();
+
+    let Some(mut session) = Session::open(url, &mut case).await else {
+        return logger.record_case("auth: challenge", case);
+    };
+
+    // Relays may only challenge clients once they attempt something that requires authentication
+    let subscription = session.subscribe(keys, timeout, &mut case).await;
+
+    let Some(event) = sign_event(
+        keys,
+        EventBuilder::new_text_note("nostr-relay-tester: nip42 authentication", []),
+        Timestamp::now(),
+        &mut case,
+    ) else {
+        return logger.record_case("auth: challenge", case);
+    };
+
+    let ok = session.send_event("EVENT", &event, timeout, &mut case).await;
+    let challenge = session.challenge(timeout, &mut case).await;
+
+    logger.record_case("auth: challenge", case);
+
+    let Some(challenge) = challenge else {
+        return session.connection.close().await;
+    };
+
+    let mut case = logger.case();
+    let authenticated = authenticate(&mut session, keys, &challenge, url, timeout, &mut case).await;
+    logger.record_case("auth: valid response", case);
+
+    let refused_subscription = subscription.filter(|answer| answer[0] == "CLOSED");
+    let refused_event = ok.filter(|ok| ok[2].as_bool() == Some(false));
+
+    if refused_subscription.is_none() && refused_event.is_none() {
+        logger.log(LogEvent::AuthenticationNotRequired);
+        return session.connection.close().await;
+    }
+
+    if !authenticated {
+        return session.connection.close().await;
+    }
+
+    let mut before = logger.case();
+    let mut after = logger.case();
+
+    if let Some(refusal) = refused_subscription {
+        let refusal = refusal[2].as_str().unwrap_or_default();
+
+        if let Some(answer) = session.subscribe(keys, timeout, &mut after).await {
+            let message = answer[2].as_str().unwrap_or_default();
+
+            if answer[0] == "EOSE" {
+                check_auth_required_prefix("CLOSED", refusal, &mut before);
+            } else {
+                after.log(LogEvent::UnexpectedlyClosedSubscription {
+                    name: SUBSCRIPTION_NAME,
+                    id: &SubscriptionId::new(answer[1].as_str().unwrap_or_default()),
+                    prefix: Prefix::parse(message).ok(),
+                    message,
+                });
+            }
+        }
+    }
+
+    if let Some(refusal) = refused_event {
+        let refusal = refusal[3].as_str().unwrap_or_default();
+
+        if let Some(ok) = session.send_event("EVENT", &event, timeout, &mut after).await {
+            let message = ok[3].as_str().unwrap_or_default();
+
+            if ok[2].as_bool() == Some(true) {
+                check_auth_required_prefix("OK", refusal, &mut before);
+            } else {
+                after.log(LogEvent::RefusedEvent {
+                    id: &event.id,
+                    prefix: Prefix::parse(message).ok(),
+                    message,
+                });
+            }
+        }
+    }
+
+    logger.record_case("auth: required before access", before);
+    logger.record_case("auth: access after auth", after);
+
+    session.connection.close().await;
+}
+
+/// Answers a challenge with an invalid authentication event, which must be
+/// rejected.
+async fn test_invalid_response(
+    url: &str,
+    keys: &Keys,
+    invalid: InvalidResponse,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let Some(mut session) = Session::open(url, logger).await else {
+        return;
+    };
+
+    // Asking for something gets lazy relays to send their challenge
+    session.subscribe(keys, timeout, logger).await;
+
+    let Some(challenge) = session.challenge(timeout, logger).await else {
+        return session.connection.close().await;
+    };
+
+    let Some(event) = invalid.sign(keys, &challenge, url, logger) else {
+        return session.connection.close().await;
+    };
+
+    let reason = invalid.reason();
+
+    if let Some(ok) = session.send_event("AUTH", &event, timeout, logger).await {
+        let message = ok[3].as_str().unwrap_or_default();
+
+        let what = format!("authentication with {reason}");
+
+        if ok[2].as_bool() == Some(false) {
+            logger.log(LogEvent::RejectedInvalid { what: &what, message });
+        } else {
+            logger.log(LogEvent::AcceptedForbidden(&format!("{what}: {ok}")));
+        }
+    }
+
+    session.connection.close().await;
+}
+
+/// Answers the challenge with a valid authentication event, returning
+/// whether the relay accepted it.
+pub(super) async fn authenticate(
+    session: &mut Session,
+    keys: &Keys,
+    challenge: &str,
+    url: &str,
+    timeout: Duration,
+    logger: &mut Logger,
+) -> bool {
+    let Some(event) = sign_event(keys, response(challenge, url), Timestamp::now(), logger) else {
+        return false;
+    };
+
+    let Some(ok) = session.send_event("AUTH", &event, timeout, logger).await else {
+        return false;
+    };
+
+    let message = ok[3].as_str().unwrap_or_default();
+
+    if ok[2].as_bool() == Some(true) {
+        logger.log(LogEvent::Authenticated(&keys.public_key()));
+        true
+    } else {
+        let prefix = check_prefix("OK", message, logger);
+        logger.log(LogEvent::RefusedAuthentication { prefix, message });
+        false
+    }
+}
+
+/// Something the relay refused before authentication worked afterwards, so it
+/// should have been refused with `auth-required:`.
+fn check_auth_required_prefix(verb: &'static str, message: &str, logger: &mut Logger) {
+    if Prefix::parse(message).ok() != Some(Prefix::AuthRequired) {
+        // Always an error, authenticating is what it took to get served
+        logger.log(LogEvent::MissingExpectedPrefix {
+            what: &format!("an unauthenticated client with {verb}, then served it once authenticated"),
+            expected: "`auth-required:`",
+            prefix: None,
+            message,
+        });
+    }
+}
+
+/// A kind 22242 event answering the challenge for the relay.
+fn response(challenge: &str, relay_url: &str) -> EventBuilder {
+    EventBuilder::new(
+        Kind::Authentication,
+        "",
+        [
+            Tag::Challenge(challenge.to_owned()),
+            Tag::Relay(UncheckedUrl::from(relay_url)),
+        ],
+    )
+}
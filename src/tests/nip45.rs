@@ -0,0 +1,201 @@
+use nostr::serde_json::{json, Value};
+
+use crate::tests::{prelude::*, raw::RawConnection};
+
+/// Kind of the events seeded next to the text notes, so that counts differ
+/// per kind.
+const SEEDED_KIND: u64 = 4_445;
+
+/// How many text notes and how many events of [`SEEDED_KIND`] are seeded.
+const SEEDED_NOTES: usize = 3;
+const SEEDED_OTHERS: usize = 2;
+
+/// Seeds events from a throwaway author, then counts them through `COUNT`
+/// with various filters. Every answer must be a `COUNT` echoing the
+/// subscription ID with the exact count, unless flagged as approximate.
+/// Relays that won't count must say so with a `CLOSED`, or a `NOTICE` naming
+/// the subscription, which leaves the test inconclusive rather than failing
+/// it.
+pub async fn test(_client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
+    let span = span!(Level::INFO, "nip45: event counts").entered();
+
+    let mut logger = Logger::new(Nips::Nip45);
+    let mut connection = SharedConnection::new(relay.url().as_str());
+
+    let keys = Keys::generate();
+    let author = keys.public_key();
+    let base = Timestamp::now() - 10_u64;
+
+    let notes = (0..SEEDED_NOTES).map(|index| (Kind::TextNote, index));
+    let others = (0..SEEDED_OTHERS).map(|index| (Kind::from(SEEDED_KIND), index));
+
+    for (offset, (kind, index)) in notes.chain(others).enumerate() {
+        let builder = EventBuilder::new(
+            kind,
+            format!("nostr-relay-tester: nip45 (kind {} #{index})", kind.as_u64()),
+            [],
+        );
+
+        if publish_event(
+            relay,
+            &mut connection,
+            &keys,
+            builder,
+            base + offset as u64,
+            timeout,
+            &mut logger,
+        )
+        .await
+        .is_none()
+        {
+            connection.close().await;
+            drop(span);
+            return logger.into();
+        }
+    }
+
+    let cases = [
+        (
+            "single filter",
+            vec![json!({ "authors": [author], "kinds": [Kind::TextNote.as_u64()] })],
+            SEEDED_NOTES,
+        ),
+        (
+            "every kind",
+            vec![json!({ "authors": [author] })],
+            SEEDED_NOTES + SEEDED_OTHERS,
+        ),
+        (
+            "multiple filters",
+            vec![
+                json!({ "authors": [author], "kinds": [Kind::TextNote.as_u64()] }),
+                json!({ "authors": [author], "kinds": [SEEDED_KIND] }),
+            ],
+            SEEDED_NOTES + SEEDED_OTHERS,
+        ),
+        (
+            "overlapping filters",
+            vec![
+                json!({ "authors": [author] }),
+                json!({ "authors": [author], "kinds": [Kind::TextNote.as_u64()] }),
+            ],
+            SEEDED_NOTES + SEEDED_OTHERS,
+        ),
+        (
+            "no match",
+            vec![json!({ "authors": [author], "kinds": [SEEDED_KIND + 1] })],
+            0,
+        ),
+    ];
+
+    if let Some(raw) = connection.get(&mut logger).await {
+        for (name, filters, expected) in cases {
+            let mut case = logger.case();
+            test_count(raw, name, filters, expected, timeout, &mut case).await;
+            logger.record_case(format!("count: {name}"), case);
+        }
+    }
+
+    connection.close().await;
+
+    drop(span);
+
+    logger.into()
+}
+
+async fn test_count(
+    connection: &mut RawConnection,
+    name: &str,
+    filters: Vec<Value>,
+    expected: usize,
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    let id = SubscriptionId::generate();
+    let mut message = vec![json!("COUNT"), json!(id)];
+    message.extend(filters);
+
+    if !connection.send_logged(Value::from(message).to_string(), logger).await {
+        return;
+    }
+
+    let request = format!("the COUNT for {name}");
+    let id_string = id.to_string();
+
+    let Some(answer) = connection
+        .read_until(&format!("COUNT for {name}"), timeout, logger, |message, logger| {
+            match message[0].as_str() {
+                Some("COUNT" | "CLOSED") if message[1].as_str() == Some(&id_string) => true,
+                // Likely a late answer to an earlier case, which mustn't be taken for this one's
+                Some("COUNT") => {
+                    logger.log(LogEvent::UnexpectedReply {
+                        name: &request,
+                        expected: "a COUNT echoing its subscription ID",
+                        reply: message,
+                    });
+                    false
+                }
+                // Notices aren't tied to a subscription, only one naming it is taken as an answer
+                Some("NOTICE") => message[1].as_str().is_some_and(|notice| notice.contains(&id_string)),
+                _ => false,
+            }
+        })
+        .await
+    else {
+        return;
+    };
+
+    if answer[0] == "NOTICE" {
+        let message = answer[1].as_str().unwrap_or_default();
+        return logger.log(LogEvent::Skipped(&format!(
+            "relay answered the COUNT for {name} with a NOTICE: {message}"
+        )));
+    }
+
+    if answer[0] == "CLOSED" {
+        let message = answer[2].as_str().unwrap_or_default();
+
+        return match check_prefix("CLOSED", message, logger) {
+            Some(prefix) if prefix.is_policy() => logger.log(LogEvent::UnexpectedlyClosedSubscription {
+                name,
+                id: &id,
+                prefix: Some(prefix),
+                message,
+            }),
+            _ => logger.log(LogEvent::Skipped(&format!(
+                "relay closed the COUNT for {name} without counting: {message}"
+            ))),
+        };
+    }
+
+    let approximate = match &answer[2]["approximate"] {
+        Value::Null => false,
+        Value::Bool(approximate) => *approximate,
+        _ => {
+            logger.log(LogEvent::UnexpectedReply {
+                name: &request,
+                expected: "a COUNT with a boolean `approximate`",
+                reply: &answer,
+            });
+            false
+        }
+    };
+
+    let Some(count) = answer[2]["count"].as_u64() else {
+        return logger.log(LogEvent::UnexpectedReply {
+            name: &request,
+            expected: "a COUNT with a non-negative integer `count`",
+            reply: &answer,
+        });
+    };
+
+    logger.log(LogEvent::ReceivedCount {
+        name,
+        count,
+        approximate,
+    });
+
+    if count != expected as u64 && !approximate {
+        logger.log(LogEvent::MismatchedCount { name, expected, count });
+    }
+}
@@ -0,0 +1,161 @@
+use nostr::Filter;
+
+use crate::tests::prelude::*;
+
+/// Kind of the matching event seeded next to the text notes, so that `kinds`
+/// has something to exclude.
+const SEEDED_KIND: u64 = 4_450;
+
+/// Limit of every search, well above the number of matching events, so that
+/// relays ignoring `search` don't send back everything they have.
+const SEARCH_LIMIT: usize = 20;
+
+const SEARCH_QUERY_NAME: &str = "search";
+const KINDS_QUERY_NAME: &str = "search with kinds";
+const AUTHORS_QUERY_NAME: &str = "search with authors";
+
+/// Seeds notes containing a word made up for the run, from two throwaway
+/// authors and of two kinds, along with one that doesn't contain it. Searching
+/// for the word must return exactly the matching notes, and nothing else, be it
+/// the non-matching note or everything the relay has, which is what relays
+/// ignoring `search` would return. Searching with `kinds` or `authors` must
+/// narrow the results down accordingly.
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
+    let span = span!(Level::INFO, "nip50: search").entered();
+
+    let mut logger = Logger::new(Nips::Nip50);
+    let mut connection = SharedConnection::new(relay.url().as_str());
+
+    let keys = Keys::generate();
+    let other_keys = Keys::generate();
+
+    let word = format!("nrt{}", &keys.public_key().to_string()[..12]);
+    let other_word = format!("nrt{}", &other_keys.public_key().to_string()[..12]);
+
+    let seeds = [
+        (&keys, Kind::TextNote, &word),
+        (&keys, Kind::from(SEEDED_KIND), &word),
+        (&other_keys, Kind::TextNote, &word),
+        (&keys, Kind::TextNote, &other_word),
+    ];
+
+    let base = Timestamp::now() - 10_u64;
+    let mut seeded = vec![];
+
+    for (offset, (keys, kind, word)) in seeds.into_iter().enumerate() {
+        let builder = EventBuilder::new(kind, format!("nostr-relay-tester: nip50 {word}"), []);
+
+        match publish_event(
+            relay,
+            &mut connection,
+            keys,
+            builder,
+            base + offset as u64,
+            timeout,
+            &mut logger,
+        )
+        .await
+        {
+            Some(event) => seeded.push(event.id),
+            None => {
+                connection.close().await;
+                drop(span);
+                return logger.into();
+            }
+        }
+    }
+
+    // The non-matching note is only there to be left out
+    let (note, other_kind, other_author) = (seeded[0], seeded[1], seeded[2]);
+
+    let mut matching = logger.case();
+    let mut excluded = logger.case();
+
+    let events = fetch_stored_events(
+        SEARCH_QUERY_NAME,
+        client,
+        relay,
+        vec![Filter::new().search(&word).limit(SEARCH_LIMIT)],
+        &EventCheck::new(),
+        timeout,
+        &mut matching,
+    )
+    .await;
+
+    if let Some(events) = events {
+        let expected = [note, other_kind, other_author];
+
+        for id in &expected {
+            if !events.iter().any(|event| event.id == *id) {
+                matching.log(LogEvent::MissingStoredEvent {
+                    name: SEARCH_QUERY_NAME,
+                    id,
+                });
+            }
+        }
+
+        let count = events.iter().filter(|event| !expected.contains(&event.id)).count();
+
+        if count > 0 {
+            excluded.log(LogEvent::UnexpectedStoredEvents {
+                name: SEARCH_QUERY_NAME,
+                count,
+            });
+        }
+    }
+
+    logger.record_case("search: matching events", matching);
+    logger.record_case("search: non-matching events", excluded);
+
+    let mut case = logger.case();
+    test_search(
+        client,
+        relay,
+        KINDS_QUERY_NAME,
+        Filter::new().search(&word).kind(Kind::TextNote).limit(SEARCH_LIMIT),
+        &[note, other_author],
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("search: with kinds", case);
+
+    let mut case = logger.case();
+    test_search(
+        client,
+        relay,
+        AUTHORS_QUERY_NAME,
+        Filter::new()
+            .search(&word)
+            .author(keys.public_key())
+            .limit(SEARCH_LIMIT),
+        &[note, other_kind],
+        timeout,
+        &mut case,
+    )
+    .await;
+    logger.record_case("search: with authors", case);
+
+    connection.close().await;
+
+    drop(span);
+
+    logger.into()
+}
+
+/// Searches with the filter, which must return exactly the expected events.
+async fn test_search(
+    client: &NostrClient,
+    relay: &Relay,
+    name: &str,
+    filter: Filter,
+    expected: &[EventId],
+    timeout: Duration,
+    logger: &mut Logger,
+) {
+    if let Some(events) =
+        fetch_stored_events(name, client, relay, vec![filter], &EventCheck::new(), timeout, logger).await
+    {
+        verify_stored_events(name, &events, expected, logger);
+    }
+}
@@ -0,0 +1,139 @@
+use nostr::{Tag, TagKind};
+
+use crate::tests::{
+    nip42::{authenticate, Session},
+    prelude::*,
+};
+
+/// Publishes protected events over a raw connection: one before
+/// authenticating, which must be rejected with `auth-required:` or
+/// `restricted:`, then one after authenticating as its author, which must be
+/// accepted, and one by someone else, which must be rejected.
+pub async fn test(client: &NostrClient, relay: &Relay, timeout: Duration) -> TestReport {
+    let span = span!(Level::INFO, "nip70: protected events").entered();
+
+    let mut logger = Logger::new(Nips::Nip70);
+    let keys = client.keys().await;
+    let url = relay.url().to_string();
+
+    let mut case = logger.case();
+
+    let Some(mut session) = Session::open(&url, &mut case).await else {
+        logger.record_case("protected: unauthenticated", case);
+        drop(span);
+        return logger.into();
+    };
+
+    test_unauthenticated(&mut session, &keys, timeout, &mut case).await;
+    logger.record_case("protected: unauthenticated", case);
+
+    let mut case = logger.case();
+
+    let authenticated = match session.challenge(timeout, &mut case).await {
+        Some(challenge) => authenticate(&mut session, &keys, &challenge, &url, timeout, &mut case).await,
+        None => false,
+    };
+
+    if authenticated {
+        test_authenticated(&mut session, &keys, timeout, &mut case).await;
+    }
+
+    logger.record_case("protected: authenticated as author", case);
+
+    if authenticated {
+        let mut case = logger.case();
+        test_foreign(&mut session, timeout, &mut case).await;
+        logger.record_case("protected: someone else's event", case);
+    }
+
+    session.connection.close().await;
+
+    drop(span);
+
+    logger.into()
+}
+
+async fn test_unauthenticated(session: &mut Session, keys: &Keys, timeout: Duration, logger: &mut Logger) {
+    let Some(event) = sign_event(keys, protected("unauthenticated"), Timestamp::now(), logger) else {
+        return;
+    };
+
+    let Some(ok) = session.send_event("EVENT", &event, timeout, logger).await else {
+        return;
+    };
+
+    let message = ok[3].as_str().unwrap_or_default();
+
+    if ok[2].as_bool() != Some(false) {
+        return logger.log(LogEvent::AcceptedForbidden(&format!(
+            "protected event {} from an unauthenticated client",
+            event.id
+        )));
+    }
+
+    match check_prefix("OK", message, logger) {
+        Some(Prefix::AuthRequired | Prefix::Restricted) => logger.log(LogEvent::RejectedEvent(&event.id, message)),
+        prefix => logger.log(LogEvent::MissingExpectedPrefix {
+            what: "a protected event from an unauthenticated client",
+            expected: "`auth-required:` or `restricted:`",
+            prefix,
+            message,
+        }),
+    }
+}
+
+async fn test_authenticated(session: &mut Session, keys: &Keys, timeout: Duration, logger: &mut Logger) {
+    let Some(event) = sign_event(keys, protected("authenticated as author"), Timestamp::now(), logger) else {
+        return;
+    };
+
+    let Some(ok) = session.send_event("EVENT", &event, timeout, logger).await else {
+        return;
+    };
+
+    let message = ok[3].as_str().unwrap_or_default();
+
+    if ok[2].as_bool() == Some(true) {
+        logger.log(LogEvent::PublishedEvent(&event.id));
+    } else {
+        let prefix = check_prefix("OK", message, logger);
+
+        logger.log(LogEvent::RefusedEvent {
+            id: &event.id,
+            prefix,
+            message,
+        });
+    }
+}
+
+/// Publishes a protected event by a throwaway author other than the one the
+/// session is authenticated as.
+async fn test_foreign(session: &mut Session, timeout: Duration, logger: &mut Logger) {
+    let Some(event) = sign_event(&Keys::generate(), protected("someone else's"), Timestamp::now(), logger) else {
+        return;
+    };
+
+    let Some(ok) = session.send_event("EVENT", &event, timeout, logger).await else {
+        return;
+    };
+
+    let message = ok[3].as_str().unwrap_or_default();
+
+    if ok[2].as_bool() == Some(false) {
+        check_prefix("OK", message, logger);
+        logger.log(LogEvent::RejectedEvent(&event.id, message));
+    } else {
+        logger.log(LogEvent::AcceptedForbidden(&format!(
+            "protected event {} from a client authenticated as someone else",
+            event.id
+        )));
+    }
+}
+
+/// A text note carrying the NIP-70 `["-"]` tag.
+fn protected(description: &str) -> EventBuilder {
+    EventBuilder::new_text_note(
+        format!("nostr-relay-tester: nip70 ({description})"),
+        [Tag::Generic(TagKind::Custom("-".to_owned()), vec![])],
+    )
+}
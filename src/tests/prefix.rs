@@ -0,0 +1,141 @@
+use std::fmt::{Display, Formatter};
+
+use crate::tests::logger::{LogEvent, Logger};
+
+/// Machine-readable prefixes relays start the message of a rejecting `OK` or
+/// a `CLOSED` with, as standardized by NIP-01.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Prefix {
+    Duplicate,
+    Pow,
+    Blocked,
+    RateLimited,
+    Invalid,
+    Restricted,
+    AuthRequired,
+    Error,
+}
+
+impl Prefix {
+    const ALL: [Prefix; 8] = [
+        Prefix::Duplicate,
+        Prefix::Pow,
+        Prefix::Blocked,
+        Prefix::RateLimited,
+        Prefix::Invalid,
+        Prefix::Restricted,
+        Prefix::AuthRequired,
+        Prefix::Error,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Prefix::Duplicate => "duplicate",
+            Prefix::Pow => "pow",
+            Prefix::Blocked => "blocked",
+            Prefix::RateLimited => "rate-limited",
+            Prefix::Invalid => "invalid",
+            Prefix::Restricted => "restricted",
+            Prefix::AuthRequired => "auth-required",
+            Prefix::Error => "error",
+        }
+    }
+
+    /// Whether a rejection with this prefix comes down to the relay's policy
+    /// (payment, whitelists, rate limits...) rather than to anything wrong
+    /// with what was sent. Such rejections make a test inconclusive instead
+    /// of failing it.
+    pub fn is_policy(&self) -> bool {
+        matches!(
+            self,
+            Prefix::Pow | Prefix::Blocked | Prefix::RateLimited | Prefix::Restricted | Prefix::AuthRequired
+        )
+    }
+
+    /// Parses the prefix of a rejection message, i.e. everything before the
+    /// first colon.
+    pub fn parse(message: &str) -> Result<Prefix, PrefixError<'_>> {
+        let (prefix, _) = message.split_once(':').ok_or(PrefixError::Missing)?;
+
+        Prefix::ALL
+            .into_iter()
+            .find(|known| known.as_str() == prefix)
+            .ok_or(PrefixError::Unknown(prefix))
+    }
+}
+
+impl Display for Prefix {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.pad(&format!("{}:", self.as_str()))
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum PrefixError<'a> {
+    Missing,
+    Unknown(&'a str),
+}
+
+/// Parses the prefix of the message a relay rejected something with, logging
+/// it if it's missing or unknown.
+pub fn check_prefix(verb: &'static str, message: &str, logger: &mut Logger) -> Option<Prefix> {
+    match Prefix::parse(message) {
+        Ok(prefix) => Some(prefix),
+        Err(PrefixError::Missing) => {
+            logger.log(LogEvent::MissingPrefix { verb, message });
+            None
+        }
+        Err(PrefixError::Unknown(prefix)) => {
+            logger.log(LogEvent::UnknownPrefix { verb, prefix, message });
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Nips;
+
+    #[test]
+    fn parses_every_known_prefix() {
+        for prefix in Prefix::ALL {
+            let message = format!("{}: some reason", prefix.as_str());
+            assert_eq!(Prefix::parse(&message), Ok(prefix));
+        }
+    }
+
+    #[test]
+    fn parses_prefix_without_reason() {
+        assert_eq!(Prefix::parse("duplicate:"), Ok(Prefix::Duplicate));
+    }
+
+    #[test]
+    fn rejects_unknown_prefix() {
+        assert_eq!(Prefix::parse("spam: go away"), Err(PrefixError::Unknown("spam")));
+        assert_eq!(
+            Prefix::parse("Blocked: case matters"),
+            Err(PrefixError::Unknown("Blocked"))
+        );
+    }
+
+    #[test]
+    fn rejects_missing_prefix() {
+        assert_eq!(Prefix::parse("go away"), Err(PrefixError::Missing));
+        assert_eq!(Prefix::parse(""), Err(PrefixError::Missing));
+    }
+
+    #[test]
+    fn check_prefix_logs_only_bad_prefixes() {
+        let check = |message| {
+            let mut logger = Logger::new(Nips::Nip01);
+            let prefix = check_prefix("OK", message, &mut logger);
+            (prefix, logger.logged().join(", "))
+        };
+
+        assert_eq!(check("blocked: paid relay"), (Some(Prefix::Blocked), String::new()));
+        assert_eq!(check("spam: go away"), (None, "UnknownPrefix".to_owned()));
+        assert_eq!(check("go away"), (None, "MissingPrefix".to_owned()));
+        assert_eq!(check(""), (None, "MissingPrefix".to_owned()));
+    }
+}
@@ -0,0 +1,291 @@
+use std::time::Duration;
+
+use futures_util::{SinkExt, StreamExt};
+use nostr::{
+    serde_json::{self, json, Value},
+    EventId, SubscriptionId,
+};
+use tokio::net::TcpStream;
+use tokio_tungstenite::{
+    connect_async,
+    tungstenite::{self, protocol::CloseFrame, Message},
+    MaybeTlsStream, WebSocketStream,
+};
+
+use crate::tests::{
+    logger::{LogEvent, Logger},
+    prefix::check_prefix,
+};
+
+/// A WebSocket connection to the relay that bypasses nostr-sdk, for sending
+/// arbitrary frames the SDK would never produce and seeing every frame the
+/// relay sends back, including the ones the SDK would drop or normalize.
+pub struct RawConnection {
+    stream: WebSocketStream<MaybeTlsStream<TcpStream>>,
+}
+
+/// A frame received from the relay, as-is.
+pub enum Frame {
+    /// A text frame holding a JSON array, as every relay message should be.
+    Message(Value),
+    /// A text frame that isn't a JSON array.
+    Malformed(String),
+    Binary(Vec<u8>),
+    /// Pings are answered by tungstenite itself, their payload is of no use.
+    Ping,
+    Pong(Vec<u8>),
+    Close(Option<CloseFrame<'static>>),
+}
+
+impl RawConnection {
+    pub async fn connect(url: &str) -> tungstenite::Result<RawConnection> {
+        let (stream, _) = connect_async(url).await?;
+
+        Ok(RawConnection { stream })
+    }
+
+    /// Connects, logging the failure if there's one.
+    pub async fn open(url: &str, logger: &mut Logger) -> Option<RawConnection> {
+        match RawConnection::connect(url).await {
+            Ok(connection) => Some(connection),
+            Err(error) => {
+                logger.log(LogEvent::FailedToOpenRawConnection(&error));
+                None
+            }
+        }
+    }
+
+    pub async fn send_frame(&mut self, frame: Message) -> tungstenite::Result<()> {
+        self.stream.send(frame).await
+    }
+
+    pub async fn send_text<T: Into<String>>(&mut self, text: T) -> tungstenite::Result<()> {
+        self.send_frame(Message::Text(text.into())).await
+    }
+
+    /// Sends the text, logging the failure if there's one. Returns whether
+    /// the text was sent.
+    pub async fn send_logged<T: Into<String>>(&mut self, text: T, logger: &mut Logger) -> bool {
+        match self.send_text(text).await {
+            Ok(()) => true,
+            Err(error) => {
+                logger.log(LogEvent::FailedToSendRawMessage(&error));
+                false
+            }
+        }
+    }
+
+    /// Waits for the next frame from the relay, whatever it is. Returns
+    /// `None` once the connection is gone.
+    pub async fn recv_frame(&mut self) -> Option<tungstenite::Result<Frame>> {
+        loop {
+            let frame = match self.stream.next().await? {
+                Ok(Message::Text(text)) => match serde_json::from_str::<Value>(&text) {
+                    Ok(message) if message.is_array() => Frame::Message(message),
+                    _ => Frame::Malformed(text),
+                },
+                Ok(Message::Binary(bytes)) => Frame::Binary(bytes),
+                Ok(Message::Ping(_)) => Frame::Ping,
+                Ok(Message::Pong(bytes)) => Frame::Pong(bytes),
+                Ok(Message::Close(frame)) => Frame::Close(frame),
+                // Raw frames are only ever handed out while writing, never while reading
+                Ok(Message::Frame(_)) => continue,
+                Err(error) => return Some(Err(error)),
+            };
+
+            return Some(Ok(frame));
+        }
+    }
+
+    /// Reads relay messages until `stop` matches one and returns it. Frames
+    /// that aren't relay messages are logged as errors, as are a lost
+    /// connection and running out of time.
+    pub async fn read_until<F: FnMut(&Value, &mut Logger) -> bool>(
+        &mut self,
+        name: &str,
+        timeout: Duration,
+        logger: &mut Logger,
+        mut stop: F,
+    ) -> Option<Value> {
+        let reader = async {
+            loop {
+                let message = match self.recv_frame().await {
+                    Some(Ok(Frame::Message(message))) => message,
+                    Some(Ok(Frame::Malformed(text))) => {
+                        logger.log(LogEvent::MalformedRelayFrame(&text));
+                        continue;
+                    }
+                    Some(Ok(Frame::Binary(bytes))) => {
+                        logger.log(LogEvent::BinaryRelayFrame(bytes.len()));
+                        continue;
+                    }
+                    Some(Ok(Frame::Ping | Frame::Pong(_))) => continue,
+                    Some(Ok(Frame::Close(frame))) => {
+                        logger.log(LogEvent::LostRawConnection(None, frame.as_ref()));
+                        return None;
+                    }
+                    Some(Err(error)) => {
+                        logger.log(LogEvent::LostRawConnection(Some(&error), None));
+                        return None;
+                    }
+                    None => {
+                        logger.log(LogEvent::LostRawConnection(None, None));
+                        return None;
+                    }
+                };
+
+                if message[0] == "NOTICE" {
+                    logger.log(LogEvent::ReceivedNoticeEvent(message[1].as_str().unwrap_or_default()));
+                }
+
+                if stop(&message, logger) {
+                    return Some(message);
+                }
+            }
+        };
+
+        let message = tokio::time::timeout(timeout, reader).await;
+
+        if message.is_err() {
+            logger.log(LogEvent::TimedOut(name, timeout));
+        }
+
+        message.ok().flatten()
+    }
+
+    /// Sends a REQ that can't match any event and waits for its EOSE, proving
+    /// the relay still processes messages on this connection.
+    pub async fn confirm_usable(&mut self, timeout: Duration, logger: &mut Logger) -> bool {
+        const NAME: &str = "EOSE for follow-up REQ";
+
+        let id = SubscriptionId::generate();
+        let filter = json!({ "ids": ["0".repeat(64)] });
+
+        if !self.send_logged(json!(["REQ", id, filter]).to_string(), logger).await {
+            return false;
+        }
+
+        let eose = self
+            .read_until(NAME, timeout, logger, |message, _| {
+                is_end_of_stored_events(message, &id)
+            })
+            .await;
+
+        eose.is_some() && self.send_logged(json!(["CLOSE", id]).to_string(), logger).await
+    }
+
+    /// Sends a fresh REQ for the event by ID and collects every event the
+    /// relay sends on it before EOSE, closing the subscription afterwards.
+    /// Returns `None` if the relay closed the subscription instead, which is
+    /// logged, or never answered.
+    pub async fn query_by_id(
+        &mut self,
+        name: &str,
+        id: &EventId,
+        timeout: Duration,
+        logger: &mut Logger,
+    ) -> Option<Vec<Value>> {
+        let subscription_id = SubscriptionId::generate();
+
+        if !self
+            .send_logged(json!(["REQ", subscription_id, { "ids": [id] }]).to_string(), logger)
+            .await
+        {
+            return None;
+        }
+
+        let mut events = vec![];
+
+        let answer = self
+            .read_until(name, timeout, logger, |message, _| {
+                let ours = message[1].as_str() == Some(&subscription_id.to_string());
+
+                if ours && message[0] == "EVENT" {
+                    events.push(message[2].clone());
+                }
+
+                ours && (message[0] == "EOSE" || message[0] == "CLOSED")
+            })
+            .await?;
+
+        if answer[0] == "CLOSED" {
+            let message = answer[2].as_str().unwrap_or_default();
+            let prefix = check_prefix("CLOSED", message, logger);
+
+            logger.log(LogEvent::UnexpectedlyClosedSubscription {
+                name,
+                id: &subscription_id,
+                prefix,
+                message,
+            });
+
+            return None;
+        }
+
+        self.send_logged(json!(["CLOSE", subscription_id]).to_string(), logger)
+            .await;
+
+        Some(events)
+    }
+
+    pub async fn close(mut self) {
+        // The relay may already be gone, there's nothing left to test at this point either way
+        let _ = self.stream.close(None).await;
+    }
+}
+
+/// A raw connection shared by every query of a test, so that checking each
+/// published event doesn't cost a connection of its own. It's opened on first
+/// use, and reopened after a query goes wrong in case the relay dropped it.
+pub struct SharedConnection {
+    url: String,
+    connection: Option<RawConnection>,
+}
+
+impl SharedConnection {
+    pub fn new(url: &str) -> SharedConnection {
+        SharedConnection {
+            url: url.to_owned(),
+            connection: None,
+        }
+    }
+
+    /// The connection, opening it first if needed and logging the failure if
+    /// there's one.
+    pub async fn get(&mut self, logger: &mut Logger) -> Option<&mut RawConnection> {
+        if self.connection.is_none() {
+            self.connection = RawConnection::open(&self.url, logger).await;
+        }
+
+        self.connection.as_mut()
+    }
+
+    /// See [`RawConnection::query_by_id`].
+    pub async fn query_by_id(
+        &mut self,
+        name: &str,
+        id: &EventId,
+        timeout: Duration,
+        logger: &mut Logger,
+    ) -> Option<Vec<Value>> {
+        let events = self.get(logger).await?.query_by_id(name, id, timeout, logger).await;
+
+        if events.is_none() {
+            if let Some(connection) = self.connection.take() {
+                connection.close().await;
+            }
+        }
+
+        events
+    }
+
+    pub async fn close(self) {
+        if let Some(connection) = self.connection {
+            connection.close().await;
+        }
+    }
+}
+
+pub fn is_end_of_stored_events(message: &Value, id: &SubscriptionId) -> bool {
+    message[0] == "EOSE" && message[1].as_str() == Some(&id.to_string())
+}
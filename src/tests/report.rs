@@ -1,18 +1,337 @@
-use std::fmt::Display;
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fmt::{Display, Formatter},
+    io::IsTerminal,
+};
 
-use crate::config::Nips;
+use once_cell::sync::Lazy;
+
+use crate::{config::Nips, tests::prefix::Prefix};
 
 type Nip = Nips;
 
 pub type Errors = Vec<color_eyre::eyre::Error>;
 
-pub enum TestReport {
-    Passed(Nip),
-    Failed { nip: Nip, errors: Errors },
+/// Only emit ANSI escape codes when a human is likely to be reading the output.
+static COLORED_OUTPUT: Lazy<bool> =
+    Lazy::new(|| std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none());
+
+/// Relay refusals grounded in its policy rather than its conformance, along
+/// with the prefix it gave for each.
+pub type Refusals = Vec<(Prefix, color_eyre::eyre::Error)>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Status {
+    Passed,
+    /// Nothing went wrong, but the relay refused some of the test on policy
+    /// grounds, e.g. with `blocked:` on a paid relay, or some of it couldn't
+    /// be carried out at all.
+    Inconclusive,
+    Failed,
+}
+
+impl Display for Status {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Status::Passed => write!(f, "{}", Colored(Color::Green, "PASS")),
+            Status::Inconclusive => write!(f, "{}", Colored(Color::Yellow, "INCONCLUSIVE")),
+            Status::Failed => write!(f, "{}", Colored(Color::Red, "FAIL")),
+        }
+    }
+}
+
+/// Everything a test, or one of its cases, found wrong with the relay.
+#[derive(Default)]
+pub struct Findings {
+    pub errors: Errors,
+    pub refusals: Refusals,
+    /// Checks that couldn't be carried out, e.g. for lack of a target to
+    /// check against.
+    pub skipped: Errors,
+}
+
+impl Findings {
+    pub fn status(&self) -> Status {
+        if !self.errors.is_empty() {
+            Status::Failed
+        } else if !self.refusals.is_empty() || !self.skipped.is_empty() {
+            Status::Inconclusive
+        } else {
+            Status::Passed
+        }
+    }
+
+    /// Lists errors first, then refusals grouped by prefix, then skipped
+    /// checks.
+    fn write_indented(&self, f: &mut Formatter<'_>, indent: usize) -> std::fmt::Result {
+        self.errors
+            .iter()
+            .try_for_each(|error| write!(f, "\n{:<indent$}{} {error}", "", Colored(Color::Red, "-")))?;
+
+        let mut by_prefix: BTreeMap<Prefix, Vec<&color_eyre::eyre::Error>> = BTreeMap::new();
+
+        for (prefix, refusal) in &self.refusals {
+            by_prefix.entry(*prefix).or_default().push(refusal);
+        }
+
+        for (prefix, refusals) in by_prefix {
+            write!(
+                f,
+                "\n{:<indent$}{} ({})",
+                "",
+                Colored(Color::Yellow, prefix),
+                refusals.len()
+            )?;
+
+            refusals
+                .iter()
+                .try_for_each(|refusal| write!(f, "\n{:<indent$}  {} {refusal}", "", Colored(Color::Yellow, "-")))?;
+        }
+
+        self.skipped
+            .iter()
+            .try_for_each(|skipped| write!(f, "\n{:<indent$}{} skipped: {skipped}", "", Colored(Color::Yellow, "-")))
+    }
+}
+
+/// Outcome of a single named check within a NIP's test.
+pub struct CaseReport {
+    pub name: String,
+    pub findings: Findings,
+}
+
+impl CaseReport {
+    pub fn status(&self) -> Status {
+        self.findings.status()
+    }
+}
+
+impl Display for CaseReport {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // The report aligns the statuses of all of a NIP's cases through the width
+        let width = f.width().unwrap_or(self.name.len() + 1);
+
+        write!(f, "{:<8}{:<width$}{}", "", self.name, self.status())?;
+
+        self.findings.write_indented(f, 10)
+    }
+}
+
+pub struct TestReport {
+    pub nip: Nip,
+    pub findings: Findings,
+    pub cases: Vec<CaseReport>,
+}
+
+impl TestReport {
+    /// The worst status among the test itself and all of its cases.
+    pub fn status(&self) -> Status {
+        self.cases
+            .iter()
+            .map(CaseReport::status)
+            .chain([self.findings.status()])
+            .max()
+            .unwrap_or(Status::Passed)
+    }
+
+    pub fn passed(&self) -> bool {
+        self.status() == Status::Passed
+    }
+
+    /// Whether any part of the test couldn't be carried out.
+    pub fn skipped(&self) -> bool {
+        [&self.findings]
+            .into_iter()
+            .chain(self.cases.iter().map(|case| &case.findings))
+            .any(|findings| !findings.skipped.is_empty())
+    }
+
+    /// Every prefix the relay refused any part of the test with.
+    pub fn refusal_prefixes(&self) -> BTreeSet<Prefix> {
+        [&self.findings]
+            .into_iter()
+            .chain(self.cases.iter().map(|case| &case.findings))
+            .flat_map(|findings| findings.refusals.iter().map(|(prefix, _)| *prefix))
+            .collect()
+    }
 }
 
 impl Display for TestReport {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        todo!("Pretty print report")
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let all_findings = || {
+            [&self.findings]
+                .into_iter()
+                .chain(self.cases.iter().map(|case| &case.findings))
+        };
+        let error_count: usize = all_findings().map(|findings| findings.errors.len()).sum();
+        let refusal_count: usize = all_findings().map(|findings| findings.refusals.len()).sum();
+        let skipped_count: usize = all_findings().map(|findings| findings.skipped.len()).sum();
+
+        write!(f, "{:<8}{}", self.nip, self.status())?;
+
+        let counts: Vec<String> = [
+            (error_count, "error"),
+            (refusal_count, "refusal"),
+            (skipped_count, "skipped check"),
+        ]
+        .into_iter()
+        .filter(|(count, _)| *count > 0)
+        .map(|(count, noun)| format!("{count} {noun}{}", if count == 1 { "" } else { "s" }))
+        .collect();
+
+        if !counts.is_empty() {
+            write!(f, " ({})", counts.join(", "))?;
+        }
+
+        self.findings.write_indented(f, 8)?;
+
+        let width = self
+            .cases
+            .iter()
+            .map(|case| case.name.len() + 2)
+            .max()
+            .unwrap_or_default();
+
+        self.cases.iter().try_for_each(|case| write!(f, "\n{case:width$}"))
+    }
+}
+
+/// Everything a run of the tester found out about the relay.
+pub struct TestRun {
+    pub reports: Vec<TestReport>,
+    /// How the tested NIPs line up with the relay's `supported_nips`, only
+    /// known when they were selected with `--nips auto`.
+    pub coverage: Option<Coverage>,
+    /// Claims of the relay information document, checked against the relay's
+    /// behavior. Empty unless the document was fetched.
+    pub claims: Vec<CaseReport>,
+}
+
+/// The difference between the NIPs a relay advertises and those the tester
+/// implements.
+pub struct Coverage {
+    /// Advertised, but with no tests to run.
+    pub not_covered: Vec<u16>,
+    /// Testable, but not advertised.
+    pub optional_extras: Vec<Nip>,
+}
+
+impl Display for Coverage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let list = |nips: Vec<String>| {
+            if nips.is_empty() {
+                "none".to_owned()
+            } else {
+                nips.join(", ")
+            }
+        };
+
+        writeln!(
+            f,
+            "Not covered (advertised, no tests): {}",
+            list(self.not_covered.iter().map(|nip| format!("NIP-{nip:02}")).collect())
+        )?;
+        write!(
+            f,
+            "Optional extras (testable, not advertised): {}",
+            list(self.optional_extras.iter().map(Nip::to_string).collect())
+        )
+    }
+}
+
+/// Per-NIP summary table followed by a final tally.
+pub struct TestSummary<'a>(pub &'a TestRun);
+
+impl Display for TestSummary<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let TestSummary(TestRun {
+            reports,
+            coverage,
+            claims,
+        }) = self;
+
+        for report in reports.iter() {
+            writeln!(f, "{report}")?;
+        }
+
+        if !claims.is_empty() {
+            let width = claims
+                .iter()
+                .map(|claim| claim.name.len() + 2)
+                .max()
+                .unwrap_or_default();
+
+            write!(f, "\nClaims vs reality")?;
+            claims.iter().try_for_each(|claim| write!(f, "\n{claim:width$}"))?;
+            writeln!(f)?;
+        }
+
+        if let Some(coverage) = coverage {
+            writeln!(f, "\n{coverage}")?;
+        }
+
+        write!(
+            f,
+            "\n{} NIP(s) tested: {}",
+            reports.len(),
+            Tally(reports.iter().map(TestReport::status))
+        )?;
+
+        if !claims.is_empty() {
+            write!(
+                f,
+                "\n{} claim(s) checked: {}",
+                claims.len(),
+                Tally(claims.iter().map(CaseReport::status))
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// How many of a set of statuses passed, failed and were inconclusive.
+struct Tally<I>(I);
+
+impl<I: Iterator<Item = Status> + Clone> Display for Tally<I> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let count = |status| self.0.clone().filter(|other| *other == status).count();
+        let (passed, inconclusive, failed) = (
+            count(Status::Passed),
+            count(Status::Inconclusive),
+            count(Status::Failed),
+        );
+
+        write!(
+            f,
+            "{} passed, {} failed, {} inconclusive",
+            Colored(Color::Green, passed),
+            Colored(if failed == 0 { Color::Green } else { Color::Red }, failed),
+            Colored(
+                if inconclusive == 0 { Color::Green } else { Color::Yellow },
+                inconclusive
+            )
+        )
+    }
+}
+
+#[derive(Copy, Clone)]
+enum Color {
+    Red = 31,
+    Green = 32,
+    Yellow = 33,
+}
+
+struct Colored<T>(Color, T);
+
+impl<T: Display> Display for Colored<T> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let Colored(color, content) = self;
+
+        if *COLORED_OUTPUT {
+            write!(f, "\x1b[{}m{content}\x1b[0m", *color as u8)
+        } else {
+            write!(f, "{content}")
+        }
     }
 }